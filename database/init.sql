@@ -24,9 +24,66 @@ CREATE TABLE IF NOT EXISTS `extract_history` (
   `id` int(11) NOT NULL AUTO_INCREMENT,
   `user_id` int(11) NOT NULL,
   `prompt` text DEFAULT NULL,
-  `extract_time` timestamp NOT NULL DEFAULT current_timestamp() ON UPDATE current_timestamp(),
+  `extract_time` timestamp NOT NULL DEFAULT current_timestamp(),
+  `status` int(11) NOT NULL DEFAULT 1,
+  `subscription_type` int(11) DEFAULT NULL,
+  `quota_credit_id` int(11) DEFAULT NULL,
+  `model` varchar(100) DEFAULT NULL,
+  `prompt_tokens` int(11) DEFAULT NULL,
+  `completion_tokens` int(11) DEFAULT NULL,
+  `cost_micro_usd` bigint(20) DEFAULT NULL,
   `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
   PRIMARY KEY (`id`),
+  KEY `user_id` (`user_id`),
+  KEY `extract_time` (`extract_time`),
+  KEY `quota_credit_id` (`quota_credit_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+-- 数据导出被取消选择。
+
+-- 导出  表 todo.inbound_mails 结构
+CREATE TABLE IF NOT EXISTS `inbound_mails` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `user_id` int(11) NOT NULL,
+  `dedupe_key` varchar(64) NOT NULL,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `user_id_dedupe_key` (`user_id`,`dedupe_key`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+-- 数据导出被取消选择。
+
+-- 导出  表 todo.invoices 结构
+CREATE TABLE IF NOT EXISTS `invoices` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `user_id` int(11) NOT NULL,
+  `kind` varchar(30) NOT NULL,
+  `external_id` varchar(50) NOT NULL,
+  `external_subscription_id` varchar(50) DEFAULT NULL,
+  `billing_reason` varchar(30) DEFAULT NULL,
+  `status` varchar(30) NOT NULL,
+  `currency` varchar(10) NOT NULL,
+  `total` bigint(20) NOT NULL,
+  `total_formatted` varchar(50) NOT NULL,
+  `refunded` tinyint(1) NOT NULL DEFAULT 0,
+  `invoice_url` text DEFAULT NULL,
+  `issued_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `kind_external_id` (`kind`,`external_id`),
+  KEY `user_id_issued_at` (`user_id`,`issued_at`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+-- 数据导出被取消选择。
+
+-- 导出  表 todo.license_bindings 结构
+CREATE TABLE IF NOT EXISTS `license_bindings` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `license_key_id` int(11) NOT NULL,
+  `user_id` int(11) NOT NULL,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `license_key_id` (`license_key_id`),
   KEY `user_id` (`user_id`)
 ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
 
@@ -38,6 +95,7 @@ CREATE TABLE IF NOT EXISTS `oauth2_state_storage` (
   `csrf_state` varchar(200) CHARACTER SET utf8mb4 NOT NULL,
   `pkce_code_verifier` varchar(200) CHARACTER SET utf8mb4 NOT NULL,
   `return_url` varchar(200) CHARACTER SET utf8mb4 NOT NULL,
+  `referral_code` varchar(50) DEFAULT NULL,
   `created_at` timestamp NOT NULL DEFAULT current_timestamp() ON UPDATE current_timestamp(),
   PRIMARY KEY (`id`)
 ) ENGINE=InnoDB DEFAULT CHARSET=latin1;
@@ -60,6 +118,117 @@ CREATE TABLE IF NOT EXISTS `orders` (
 
 -- 数据导出被取消选择。
 
+-- 导出  表 todo.personal_access_tokens 结构
+CREATE TABLE IF NOT EXISTS `personal_access_tokens` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `user_id` int(11) NOT NULL,
+  `name` varchar(100) NOT NULL,
+  `token_prefix` varchar(20) NOT NULL,
+  `token_hash` varchar(64) NOT NULL,
+  `scopes` varchar(255) NOT NULL,
+  `expires_at` datetime DEFAULT NULL,
+  `last_used_at` timestamp NULL DEFAULT NULL,
+  `revoked_at` timestamp NULL DEFAULT NULL,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `token_hash` (`token_hash`),
+  KEY `user_id` (`user_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+-- 数据导出被取消选择。
+
+-- 导出  表 todo.plans 结构
+CREATE TABLE IF NOT EXISTS `plans` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `name` varchar(100) NOT NULL,
+  `description` text DEFAULT NULL,
+  `type` int(11) NOT NULL,
+  `kind` int(11) NOT NULL DEFAULT 1,
+  `product_id` int(11) DEFAULT NULL,
+  `variant_id` int(11) DEFAULT NULL,
+  `price` varchar(50) DEFAULT NULL,
+  `quota` int(11) NOT NULL,
+  `credit_validity_days` int(11) DEFAULT NULL,
+  `models` varchar(500) NOT NULL,
+  `features` text DEFAULT NULL,
+  `is_public` tinyint(1) NOT NULL DEFAULT 1,
+  `sort_order` int(11) NOT NULL DEFAULT 0,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `variant_id` (`variant_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+INSERT IGNORE INTO `plans` (`id`, `name`, `description`, `type`, `product_id`, `variant_id`, `price`, `quota`, `models`, `features`, `is_public`, `sort_order`) VALUES
+	(1, 'Free Plan', NULL, 1, NULL, NULL, NULL, 10, 'gpt-3.5-turbo', '[]', 1, 0),
+	(2, 'Pro Plan', NULL, 2, 120215, 138344, NULL, 125, 'gpt-4o', '[]', 1, 1);
+
+-- 导出  表 todo.quota_credits 结构
+CREATE TABLE IF NOT EXISTS `quota_credits` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `user_id` int(11) NOT NULL,
+  `plan_id` int(11) DEFAULT NULL,
+  `external_order_id` varchar(50) NOT NULL,
+  `credits` int(11) NOT NULL,
+  `expires_at` timestamp NULL DEFAULT NULL,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `external_order_id` (`external_order_id`),
+  KEY `user_id` (`user_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+-- 数据导出被取消选择。
+
+-- 导出  表 todo.referrals 结构
+CREATE TABLE IF NOT EXISTS `referrals` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `referrer_user_id` int(11) NOT NULL,
+  `referee_user_id` int(11) NOT NULL,
+  `discount_id` int(11) DEFAULT NULL,
+  `discount_code` varchar(50) DEFAULT NULL,
+  `status` int(11) NOT NULL DEFAULT 0,
+  `rewarded_at` timestamp NULL DEFAULT NULL,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `referee_user_id` (`referee_user_id`),
+  KEY `referrer_user_id` (`referrer_user_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+-- 数据导出被取消选择。
+
+-- 导出  表 todo.refresh_tokens 结构
+CREATE TABLE IF NOT EXISTS `refresh_tokens` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `user_id` int(11) NOT NULL,
+  `token_hash` varchar(64) NOT NULL,
+  `session_id` varchar(36) NOT NULL,
+  `replaced_by_id` int(11) DEFAULT NULL,
+  `expires_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  `revoked_at` timestamp NULL DEFAULT NULL,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `token_hash` (`token_hash`),
+  KEY `session_id` (`session_id`),
+  KEY `user_id` (`user_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+-- 数据导出被取消选择。
+
+-- 导出  表 todo.sessions 结构
+CREATE TABLE IF NOT EXISTS `sessions` (
+  `id` varchar(36) NOT NULL,
+  `user_id` int(11) NOT NULL,
+  `user_agent` varchar(255) DEFAULT NULL,
+  `ip_address` varchar(45) DEFAULT NULL,
+  `expires_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  `last_used_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  `revoked_at` timestamp NULL DEFAULT NULL,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  KEY `user_id` (`user_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+-- 数据导出被取消选择。
+
 -- 导出  表 todo.todos 结构
 CREATE TABLE IF NOT EXISTS `todos` (
   `id` int(11) NOT NULL AUTO_INCREMENT,
@@ -81,18 +250,48 @@ CREATE TABLE IF NOT EXISTS `todos` (
 CREATE TABLE IF NOT EXISTS `users` (
   `id` int(11) NOT NULL AUTO_INCREMENT,
   `email` varchar(200) CHARACTER SET utf8mb4 NOT NULL,
+  `google_id` varchar(50) DEFAULT NULL,
   `first_name` varchar(200) CHARACTER SET utf8mb4 NOT NULL,
   `last_name` varchar(200) CHARACTER SET utf8mb4 NOT NULL,
   `avatar` text CHARACTER SET utf8mb4 NOT NULL,
   `google_access_token` text CHARACTER SET utf8mb4 NOT NULL,
   `google_refresh_token` text CHARACTER SET utf8mb4 NOT NULL,
   `has_google_calendar_access` tinyint(1) NOT NULL,
+  `inbound_mail_token` varchar(50) DEFAULT NULL,
+  `lemon_squeezy_customer_id` int(11) DEFAULT NULL,
+  `referral_code` varchar(50) DEFAULT NULL,
   `created_at` timestamp NOT NULL DEFAULT current_timestamp() ON UPDATE current_timestamp(),
-  PRIMARY KEY (`id`)
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `google_id` (`google_id`),
+  UNIQUE KEY `inbound_mail_token` (`inbound_mail_token`),
+  UNIQUE KEY `referral_code` (`referral_code`),
+  KEY `lemon_squeezy_customer_id` (`lemon_squeezy_customer_id`)
 ) ENGINE=InnoDB DEFAULT CHARSET=latin1;
 
 -- 数据导出被取消选择。
 
+-- 导出  表 todo.user_licenses 结构
+CREATE TABLE IF NOT EXISTS `user_licenses` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `user_id` int(11) NOT NULL,
+  `license_key_id` int(11) NOT NULL,
+  `license_key` varchar(100) NOT NULL,
+  `instance_id` varchar(100) NOT NULL,
+  `instance_name` varchar(255) NOT NULL,
+  `product_id` int(11) NOT NULL,
+  `variant_id` int(11) NOT NULL,
+  `status` varchar(50) NOT NULL,
+  `expires_at` timestamp NULL DEFAULT NULL,
+  `last_validated_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `instance_id` (`instance_id`),
+  KEY `user_id` (`user_id`),
+  KEY `license_key_id` (`license_key_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+-- 数据导出被取消选择。
+
 -- 导出  表 todo.user_subscriptions 结构
 CREATE TABLE IF NOT EXISTS `user_subscriptions` (
   `id` int(11) NOT NULL AUTO_INCREMENT,
@@ -106,6 +305,8 @@ CREATE TABLE IF NOT EXISTS `user_subscriptions` (
   `ends_at` timestamp NULL DEFAULT current_timestamp(),
   `type` int(11) NOT NULL,
   `quota` int(11) NOT NULL,
+  `previous_quota` int(11) DEFAULT NULL,
+  `quota_changed_at` timestamp NULL DEFAULT NULL,
   `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
   PRIMARY KEY (`id`),
   KEY `user_id` (`user_id`),
@@ -115,6 +316,26 @@ CREATE TABLE IF NOT EXISTS `user_subscriptions` (
 
 -- 数据导出被取消选择。
 
+-- 导出  表 todo.webhook_events 结构
+CREATE TABLE IF NOT EXISTS `webhook_events` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `source` varchar(50) NOT NULL,
+  `event_name` varchar(100) NOT NULL,
+  `dedupe_key` varchar(64) NOT NULL,
+  `payload` mediumtext NOT NULL,
+  `status` int(11) NOT NULL DEFAULT 0,
+  `attempts` int(11) NOT NULL DEFAULT 0,
+  `error` text DEFAULT NULL,
+  `next_attempt_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  `processed_at` timestamp NULL DEFAULT NULL,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `dedupe_key` (`dedupe_key`),
+  KEY `status_next_attempt_at` (`status`,`next_attempt_at`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+-- 数据导出被取消选择。
+
 /*!40103 SET TIME_ZONE=IFNULL(@OLD_TIME_ZONE, 'system') */;
 /*!40101 SET SQL_MODE=IFNULL(@OLD_SQL_MODE, '') */;
 /*!40014 SET FOREIGN_KEY_CHECKS=IFNULL(@OLD_FOREIGN_KEY_CHECKS, 1) */;
@@ -0,0 +1,41 @@
+-- --------------------------------------------------------
+-- 升级已有数据库
+-- init.sql 只会创建缺少的表，已有的表需要执行此脚本补充新增的列和索引
+-- 需要 MariaDB 10.0.2 以上，`IF NOT EXISTS` 使脚本可以重复执行
+-- --------------------------------------------------------
+
+USE `todo`;
+
+-- 升级  表 todo.extract_history 结构
+-- 旧数据都是已完成的提取，`status` 默认为 1 (committed)
+ALTER TABLE `extract_history`
+  MODIFY COLUMN `extract_time` timestamp NOT NULL DEFAULT current_timestamp(),
+  ADD COLUMN IF NOT EXISTS `status` int(11) NOT NULL DEFAULT 1 AFTER `extract_time`,
+  ADD COLUMN IF NOT EXISTS `subscription_type` int(11) DEFAULT NULL AFTER `status`,
+  ADD COLUMN IF NOT EXISTS `quota_credit_id` int(11) DEFAULT NULL AFTER `subscription_type`,
+  ADD COLUMN IF NOT EXISTS `model` varchar(100) DEFAULT NULL AFTER `quota_credit_id`,
+  ADD COLUMN IF NOT EXISTS `prompt_tokens` int(11) DEFAULT NULL AFTER `model`,
+  ADD COLUMN IF NOT EXISTS `completion_tokens` int(11) DEFAULT NULL AFTER `prompt_tokens`,
+  ADD COLUMN IF NOT EXISTS `cost_micro_usd` bigint(20) DEFAULT NULL AFTER `completion_tokens`,
+  ADD KEY IF NOT EXISTS `extract_time` (`extract_time`),
+  ADD KEY IF NOT EXISTS `quota_credit_id` (`quota_credit_id`);
+
+-- 升级  表 todo.oauth2_state_storage 结构
+ALTER TABLE `oauth2_state_storage`
+  ADD COLUMN IF NOT EXISTS `referral_code` varchar(50) DEFAULT NULL AFTER `return_url`;
+
+-- 升级  表 todo.users 结构
+ALTER TABLE `users`
+  ADD COLUMN IF NOT EXISTS `google_id` varchar(50) DEFAULT NULL AFTER `email`,
+  ADD COLUMN IF NOT EXISTS `inbound_mail_token` varchar(50) DEFAULT NULL AFTER `has_google_calendar_access`,
+  ADD COLUMN IF NOT EXISTS `lemon_squeezy_customer_id` int(11) DEFAULT NULL AFTER `inbound_mail_token`,
+  ADD COLUMN IF NOT EXISTS `referral_code` varchar(50) DEFAULT NULL AFTER `lemon_squeezy_customer_id`,
+  ADD UNIQUE KEY IF NOT EXISTS `google_id` (`google_id`),
+  ADD UNIQUE KEY IF NOT EXISTS `inbound_mail_token` (`inbound_mail_token`),
+  ADD UNIQUE KEY IF NOT EXISTS `referral_code` (`referral_code`),
+  ADD KEY IF NOT EXISTS `lemon_squeezy_customer_id` (`lemon_squeezy_customer_id`);
+
+-- 升级  表 todo.user_subscriptions 结构
+ALTER TABLE `user_subscriptions`
+  ADD COLUMN IF NOT EXISTS `previous_quota` int(11) DEFAULT NULL AFTER `quota`,
+  ADD COLUMN IF NOT EXISTS `quota_changed_at` timestamp NULL DEFAULT NULL AFTER `previous_quota`;
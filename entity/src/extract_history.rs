@@ -11,6 +11,13 @@ pub struct Model {
     #[sea_orm(column_type = "Text", nullable)]
     pub prompt: Option<String>,
     pub extract_time: DateTimeUtc,
+    pub status: i32,
+    pub subscription_type: Option<i32>,
+    pub quota_credit_id: Option<i32>,
+    pub model: Option<String>,
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+    pub cost_micro_usd: Option<i64>,
     pub created_at: DateTimeUtc,
 }
 
@@ -3,8 +3,19 @@
 pub mod prelude;
 
 pub mod extract_history;
+pub mod inbound_mails;
+pub mod invoices;
+pub mod license_bindings;
 pub mod oauth2_state_storage;
 pub mod orders;
+pub mod personal_access_tokens;
+pub mod plans;
+pub mod quota_credits;
+pub mod referrals;
+pub mod refresh_tokens;
+pub mod sessions;
 pub mod todos;
+pub mod user_licenses;
 pub mod user_subscriptions;
 pub mod users;
+pub mod webhook_events;
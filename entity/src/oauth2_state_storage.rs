@@ -10,6 +10,7 @@ pub struct Model {
     pub csrf_state: String,
     pub pkce_code_verifier: String,
     pub return_url: String,
+    pub referral_code: Option<String>,
     pub created_at: DateTimeUtc,
 }
 
@@ -1,8 +1,19 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.2
 
 pub use super::extract_history::Entity as ExtractHistory;
+pub use super::inbound_mails::Entity as InboundMails;
+pub use super::invoices::Entity as Invoices;
+pub use super::license_bindings::Entity as LicenseBindings;
 pub use super::oauth2_state_storage::Entity as Oauth2StateStorage;
 pub use super::orders::Entity as Orders;
+pub use super::personal_access_tokens::Entity as PersonalAccessTokens;
+pub use super::plans::Entity as Plans;
+pub use super::quota_credits::Entity as QuotaCredits;
+pub use super::referrals::Entity as Referrals;
+pub use super::refresh_tokens::Entity as RefreshTokens;
+pub use super::sessions::Entity as Sessions;
 pub use super::todos::Entity as Todos;
+pub use super::user_licenses::Entity as UserLicenses;
 pub use super::user_subscriptions::Entity as UserSubscriptions;
 pub use super::users::Entity as Users;
+pub use super::webhook_events::Entity as WebhookEvents;
@@ -17,6 +17,8 @@ pub struct Model {
     pub ends_at: Option<DateTimeUtc>,
     pub r#type: i32,
     pub quota: i32,
+    pub previous_quota: Option<i32>,
+    pub quota_changed_at: Option<DateTimeUtc>,
     pub created_at: DateTimeUtc,
 }
 
@@ -8,6 +8,8 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub email: String,
+    #[sea_orm(unique)]
+    pub google_id: Option<String>,
     pub first_name: String,
     pub last_name: String,
     #[sea_orm(column_type = "Text")]
@@ -17,6 +19,11 @@ pub struct Model {
     #[sea_orm(column_type = "Text")]
     pub google_refresh_token: String,
     pub has_google_calendar_access: i8,
+    #[sea_orm(unique)]
+    pub inbound_mail_token: Option<String>,
+    pub lemon_squeezy_customer_id: Option<i32>,
+    #[sea_orm(unique)]
+    pub referral_code: Option<String>,
     pub created_at: DateTimeUtc,
 }
 
@@ -1 +1,4 @@
 pub const API_HOST: &str = "https://api.lemonsqueezy.com/v1";
+
+/** Header carrying the HMAC-SHA256 signature of webhook payloads */
+pub const SIGNATURE_HEADER: &str = "x-signature";
@@ -0,0 +1,125 @@
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/** An entry of the JSON:API `errors` array */
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct ApiError {
+    pub status: Option<String>,
+    pub title: Option<String>,
+    pub detail: Option<String>,
+    pub source: Option<ApiErrorSource>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct ApiErrorSource {
+    /** JSON pointer to the invalid attribute, e.g. `/data/attributes/variant_id` */
+    pub pointer: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiErrorResponse {
+    #[serde(default)]
+    pub errors: Vec<ApiError>,
+}
+
+#[derive(Debug)]
+pub enum LemonSqueezyError {
+    /** 401 or 403, the API key is missing, invalid or lacks access */
+    Unauthorized(Vec<ApiError>),
+    /** 404 */
+    NotFound(Vec<ApiError>),
+    /** 400 or 422, the request was rejected */
+    Validation(Vec<ApiError>),
+    /** 429, `retry_after` is in seconds */
+    RateLimited { retry_after: Option<u64> },
+    /** Any other unsuccessful response */
+    Api { status: u16, errors: Vec<ApiError> },
+    /** The request could not be sent or the response could not be read */
+    Transport(reqwest::Error),
+    /** The response is not the expected JSON */
+    Decode(serde_json::Error),
+    /** The request could not be built, or the response lacks an expected field */
+    InvalidData(String),
+}
+
+impl LemonSqueezyError {
+    pub(crate) fn from_response(
+        status: reqwest::StatusCode,
+        retry_after: Option<u64>,
+        body: &str,
+    ) -> Self {
+        let errors = serde_json::from_str::<ApiErrorResponse>(body)
+            .map(|response| response.errors)
+            .unwrap_or_default();
+
+        match status.as_u16() {
+            401 | 403 => LemonSqueezyError::Unauthorized(errors),
+            404 => LemonSqueezyError::NotFound(errors),
+            400 | 422 => LemonSqueezyError::Validation(errors),
+            429 => LemonSqueezyError::RateLimited { retry_after },
+            status => LemonSqueezyError::Api { status, errors },
+        }
+    }
+
+    /** Errors reported by the API, empty for transport and decoding errors */
+    pub fn api_errors(&self) -> &[ApiError] {
+        match self {
+            LemonSqueezyError::Unauthorized(errors)
+            | LemonSqueezyError::NotFound(errors)
+            | LemonSqueezyError::Validation(errors)
+            | LemonSqueezyError::Api { errors, .. } => errors,
+            _ => &[],
+        }
+    }
+}
+
+impl fmt::Display for LemonSqueezyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let details = self
+            .api_errors()
+            .iter()
+            .filter_map(|error| error.detail.as_ref().or(error.title.as_ref()))
+            .cloned()
+            .collect::<Vec<String>>()
+            .join("; ");
+
+        match self {
+            LemonSqueezyError::Unauthorized(_) => write!(f, "Unauthorized: {}", details),
+            LemonSqueezyError::NotFound(_) => write!(f, "Not found: {}", details),
+            LemonSqueezyError::Validation(_) => write!(f, "Validation failed: {}", details),
+            LemonSqueezyError::RateLimited { retry_after } => match retry_after {
+                Some(retry_after) => write!(f, "Rate limited, retry after {}s", retry_after),
+                None => write!(f, "Rate limited"),
+            },
+            LemonSqueezyError::Api { status, .. } => {
+                write!(f, "API error with status {}: {}", status, details)
+            }
+            LemonSqueezyError::Transport(err) => write!(f, "Transport error: {}", err),
+            LemonSqueezyError::Decode(err) => write!(f, "Failed to decode response: {}", err),
+            LemonSqueezyError::InvalidData(message) => write!(f, "Invalid data: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for LemonSqueezyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LemonSqueezyError::Transport(err) => Some(err),
+            LemonSqueezyError::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for LemonSqueezyError {
+    fn from(err: reqwest::Error) -> Self {
+        LemonSqueezyError::Transport(err)
+    }
+}
+
+impl From<serde_json::Error> for LemonSqueezyError {
+    fn from(err: serde_json::Error) -> Self {
+        LemonSqueezyError::Decode(err)
+    }
+}
@@ -1,22 +1,57 @@
 use core::fmt;
 
-use anyhow::anyhow;
 use reqwest::header::{HeaderMap, HeaderValue};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
-use serde_qs;
 
 pub mod constants;
+mod error;
+mod licenses;
+mod pagination;
+mod resources;
+mod webhook;
+
+pub use error::{ApiError, ApiErrorSource, LemonSqueezyError};
+pub use licenses::{
+    ActivateLicenseResponse, DeactivateLicenseResponse, LicenseInstance, LicenseKeyInfo,
+    LicenseMeta, ValidateLicenseResponse,
+};
+pub use pagination::{
+    ListLinks, ListMeta, ListParams, ListResponse, PageMeta, PageParams, Paginator,
+};
+pub use resources::*;
+pub use webhook::{verify_signature, WebhookEnvelope, WebhookEvent, WebhookMeta, WebhookPayload};
 
 #[derive(Clone, Debug)]
 pub struct LemonSqueezy {
     client: reqwest::Client,
     headers: HeaderMap,
+    base_url: String,
 }
 
-impl LemonSqueezy {
-    pub fn new(key: String) -> Self {
-        let client = reqwest::Client::new();
+/** Builds a client, e.g. against a local mock server with `base_url` */
+#[derive(Clone, Debug)]
+pub struct LemonSqueezyBuilder {
+    key: String,
+    base_url: String,
+    client: Option<reqwest::Client>,
+}
+
+impl LemonSqueezyBuilder {
+    /** Defaults to `constants::API_HOST` */
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    /** Use a preconfigured HTTP client, e.g. with timeouts or a proxy */
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn build(self) -> LemonSqueezy {
+        let client = self.client.unwrap_or_default();
         let mut headers = HeaderMap::new();
 
         headers.append(
@@ -29,20 +64,127 @@ impl LemonSqueezy {
         );
         headers.append(
             "Authorization",
-            HeaderValue::from_str(&format!("Bearer {}", key)).unwrap(),
+            HeaderValue::from_str(&format!("Bearer {}", self.key)).unwrap(),
         );
 
-        Self { client, headers }
+        LemonSqueezy {
+            client,
+            headers,
+            base_url: self.base_url,
+        }
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl LemonSqueezy {
+    pub fn new(key: String) -> Self {
+        Self::builder(key).build()
+    }
+
+    pub fn builder(key: String) -> LemonSqueezyBuilder {
+        LemonSqueezyBuilder {
+            key,
+            base_url: constants::API_HOST.to_owned(),
+            client: None,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    fn query<T: Serialize>(params: &T) -> Result<String, LemonSqueezyError> {
+        serde_qs::to_string(params).map_err(|err| LemonSqueezyError::InvalidData(err.to_string()))
+    }
+
+    /** `Retry-After` of rate limited responses, in seconds */
+    fn retry_after(response: &reqwest::Response) -> Option<u64> {
+        response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+    }
+
+    /** Send the request and decode the JSON:API document, or the `errors` of a failed request */
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T, LemonSqueezyError> {
+        let response = request.headers(self.headers.clone()).send().await?;
+
+        let status = response.status();
+        let retry_after = Self::retry_after(&response);
+        let body = response.text().await?;
+
+        if !status.is_success() {
+            return Err(LemonSqueezyError::from_response(status, retry_after, &body));
+        }
+
+        Ok(serde_json::from_str::<T>(&body)?)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct CreateCheckoutParams {
-    pub email: Option<String>,
     pub store_id: i32,
     pub variant_id: i32,
-    pub redirect_url: String,
+    /** Prefills the checkout form */
+    pub email: Option<String>,
+    pub name: Option<String>,
+    /** Applied to the checkout, the discount must exist in the store */
+    pub discount_code: Option<String>,
+    /** Sent back as `meta.custom_data` in webhooks */
     pub custom_data: serde_json::Value,
+    /** Overrides the price of the variant, in cents */
+    pub custom_price: Option<i64>,
+    /** ISO 8601 time, the checkout link never expires when `None` */
+    pub expires_at: Option<String>,
+    pub product_options: CheckoutProductOptions,
+    pub checkout_options: CheckoutOptions,
+    /** Return the totals of the checkout in `attributes.preview` */
+    pub preview: bool,
+    pub test_mode: bool,
+}
+
+/** Overrides of the product shown at checkout and after the purchase */
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct CheckoutProductOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt_button_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt_link_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt_thank_you_note: Option<String>,
+    /** Variants selectable at checkout, all variants of the product when empty */
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub enabled_variants: Vec<i32>,
+}
+
+/** Appearance of the checkout */
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct CheckoutOptions {
+    /** Open the checkout in an overlay with Lemon.js instead of a new page */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dark: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desc: Option<bool>,
+    /** Show the discount code field */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discount: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub button_color: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -66,6 +208,38 @@ pub struct CheckoutLinks {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CheckoutAttributes {
     pub url: String,
+    pub expires_at: Option<String>,
+    #[serde(default)]
+    pub test_mode: bool,
+    /** Only present when the checkout was created with `preview` */
+    #[serde(default, deserialize_with = "deserialize_checkout_preview")]
+    pub preview: Option<CheckoutPreview>,
+}
+
+/** Totals of the checkout, amounts in cents */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CheckoutPreview {
+    pub currency: String,
+    pub subtotal: i64,
+    pub discount_total: i64,
+    pub tax: i64,
+    pub total: i64,
+    pub subtotal_formatted: String,
+    pub discount_total_formatted: String,
+    pub tax_formatted: String,
+    pub total_formatted: String,
+}
+
+/** `preview` is `false` instead of `null` when it was not requested */
+fn deserialize_checkout_preview<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<CheckoutPreview>, D::Error> {
+    match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(preview @ serde_json::Value::Object(_)) => serde_json::from_value(preview)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -81,23 +255,32 @@ impl LemonSqueezy {
     pub async fn create_checkout(
         &self,
         params: CreateCheckoutParams,
-    ) -> Result<CheckoutObject, anyhow::Error> {
-        let url = format!("{}/checkouts", constants::API_HOST);
+    ) -> Result<CheckoutObject, LemonSqueezyError> {
+        let url = self.url("/checkouts");
 
-        let response = self
-            .client
-            .post(url)
-            .json(&json!({
+        let mut checkout_data = json!({
+            "email": params.email.unwrap_or(String::from("")),
+            "custom": json!(params.custom_data),
+        });
+        if let Some(name) = params.name {
+            checkout_data["name"] = json!(name);
+        }
+        if let Some(discount_code) = params.discount_code {
+            checkout_data["discount_code"] = json!(discount_code);
+        }
+
+        let response: CreateCheckoutResponse = self
+            .send(self.client.post(url).json(&json!({
                 "data": {
                     "type": "checkouts",
                     "attributes": {
-                        "checkout_data": {
-                            "email": params.email.unwrap_or(String::from("")),
-                            "custom": json!(params.custom_data),
-                        },
-                        "product_options": {
-                            "redirect_url": params.redirect_url,
-                        }
+                        "custom_price": params.custom_price,
+                        "expires_at": params.expires_at,
+                        "preview": params.preview,
+                        "test_mode": params.test_mode,
+                        "checkout_data": checkout_data,
+                        "product_options": params.product_options,
+                        "checkout_options": params.checkout_options,
                     },
                     "relationships": {
                         "store": {
@@ -114,14 +297,10 @@ impl LemonSqueezy {
                         }
                     }
                 }
-            }))
-            .headers(self.headers.clone())
-            .send()
-            .await?
-            .json::<CreateCheckoutResponse>()
+            })))
             .await?;
 
-        return Ok(response.data);
+        Ok(response.data)
     }
 }
 
@@ -154,21 +333,42 @@ impl fmt::Display for SubscriptionStatus {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Default)]
-pub struct GetSubscriptionsParams {
-    pub store_id: i32,
-    pub order_id: i32,
-    pub product_id: i32,
-    pub variant_id: i32,
-    pub status: SubscriptionStatus,
-    pub user_email: String,
+impl std::str::FromStr for SubscriptionStatus {
+    type Err = LemonSqueezyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "on_trial" => Ok(SubscriptionStatus::OnTrial),
+            "active" => Ok(SubscriptionStatus::Active),
+            "paused" => Ok(SubscriptionStatus::Paused),
+            "past_due" => Ok(SubscriptionStatus::PastDue),
+            "unpaid" => Ok(SubscriptionStatus::Unpaid),
+            "cancelled" => Ok(SubscriptionStatus::Cancelled),
+            "expired" => Ok(SubscriptionStatus::Expired),
+            "unknown" => Ok(SubscriptionStatus::Unknown),
+            _ => Err(LemonSqueezyError::InvalidData(format!(
+                "Invalid subscription status {}",
+                s
+            ))),
+        }
+    }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct GetSubscriptionsResponse {
-    pub data: Vec<SubscriptionObject>,
+pub type GetSubscriptionsParams = ListParams<GetSubscriptionsFilter>;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct GetSubscriptionsFilter {
+    pub store_id: Option<i32>,
+    pub order_id: Option<i32>,
+    pub order_item_id: Option<i32>,
+    pub product_id: Option<i32>,
+    pub variant_id: Option<i32>,
+    pub status: Option<SubscriptionStatus>,
+    pub user_email: Option<String>,
 }
 
+pub type GetSubscriptionsResponse = ListResponse<SubscriptionObject>;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GetSubscriptionResponse {
     pub data: SubscriptionObject,
@@ -195,6 +395,29 @@ pub struct SubscriptionObjectAttributes {
     pub created_at: String,
     pub renews_at: String,
     pub ends_at: Option<String>,
+    #[serde(default)]
+    pub urls: Option<SubscriptionUrls>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubscriptionUrls {
+    pub update_payment_method: String,
+    #[serde(default)]
+    pub customer_portal: Option<String>,
+}
+
+/** Only the given fields are changed */
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct UpdateSubscriptionParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant_id: Option<i32>,
+    /** `false` resumes a cancelled subscription during its grace period */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancelled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invoice_immediately: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_prorations: Option<bool>,
 }
 
 impl LemonSqueezy {
@@ -202,46 +425,139 @@ impl LemonSqueezy {
     pub async fn get_subscription(
         &self,
         subscription_id: i32,
-    ) -> Result<SubscriptionObject, anyhow::Error> {
-        let url = format!("{}/subscriptions/{}", constants::API_HOST, subscription_id);
-
-        let response = self
-            .client
-            .get(url)
-            .headers(self.headers.clone())
-            .send()
-            .await?
-            .text()
+    ) -> Result<SubscriptionObject, LemonSqueezyError> {
+        self.retrieve("/subscriptions", subscription_id).await
+    }
+
+    /** Update subscription, e.g. change plan or resume */
+    pub async fn update_subscription(
+        &self,
+        subscription_id: i32,
+        params: UpdateSubscriptionParams,
+    ) -> Result<SubscriptionObject, LemonSqueezyError> {
+        let url = self.url(&format!("/subscriptions/{}", subscription_id));
+
+        let response: GetSubscriptionResponse = self
+            .send(self.client.patch(url).json(&json!({
+                "data": {
+                    "type": "subscriptions",
+                    "id": format!("{}", subscription_id),
+                    "attributes": params,
+                }
+            })))
             .await?;
 
-        println!("get_subscription response: {}", &response);
+        Ok(response.data)
+    }
+
+    /** Cancel subscription. It stays active until the end of the current period. */
+    pub async fn cancel_subscription(
+        &self,
+        subscription_id: i32,
+    ) -> Result<SubscriptionObject, LemonSqueezyError> {
+        let url = self.url(&format!("/subscriptions/{}", subscription_id));
+
+        let response: GetSubscriptionResponse = self.send(self.client.delete(url)).await?;
 
-        let parsed_response = serde_json::from_str::<GetSubscriptionResponse>(&response)?;
+        Ok(response.data)
+    }
+
+    /** Resume a cancelled subscription before it expires */
+    pub async fn resume_subscription(
+        &self,
+        subscription_id: i32,
+    ) -> Result<SubscriptionObject, LemonSqueezyError> {
+        self.update_subscription(
+            subscription_id,
+            UpdateSubscriptionParams {
+                cancelled: Some(false),
+                ..Default::default()
+            },
+        )
+        .await
+    }
 
-        Ok(parsed_response.data)
+    /**
+     * Signed customer portal and update payment method URLs of the subscription.
+     * The URLs expire after 24 hours, fetch them right before redirecting.
+     */
+    pub async fn get_subscription_urls(
+        &self,
+        subscription_id: i32,
+    ) -> Result<SubscriptionUrls, LemonSqueezyError> {
+        let subscription = self.get_subscription(subscription_id).await?;
+
+        subscription
+            .attributes
+            .urls
+            .ok_or(LemonSqueezyError::InvalidData(String::from(
+                "Missing urls of subscription.",
+            )))
     }
 
-    /** Get subscriptions by conditions */
+    /** Get a page of subscriptions by conditions */
     pub async fn get_subscriptions(
         &self,
         params: GetSubscriptionsParams,
-    ) -> Result<Vec<SubscriptionObject>, anyhow::Error> {
-        let query =
-            serde_qs::to_string(&params).map_err(|_| anyhow!("Failed to serialize params."))?;
-        let url = format!("{}/subscriptions?{}", constants::API_HOST, query);
-        let response = self
-            .client
-            .get(url)
-            .headers(self.headers.clone())
-            .send()
-            .await?
-            .json::<GetSubscriptionsResponse>()
-            .await?;
+    ) -> Result<GetSubscriptionsResponse, LemonSqueezyError> {
+        self.list("/subscriptions", params).await
+    }
 
-        Ok(response.data)
+    /** Walk all subscriptions matching the conditions, `params.page` sets the first page */
+    pub fn paginate_subscriptions(
+        &self,
+        params: GetSubscriptionsParams,
+    ) -> Paginator<'_, SubscriptionObject, GetSubscriptionsFilter> {
+        Paginator::new(self, "/subscriptions", params)
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderObject {
+    pub r#type: String,
+    pub id: String,
+    pub attributes: OrderObjectAttributes,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderObjectAttributes {
+    pub store_id: i32,
+    pub customer_id: i32,
+    pub identifier: String,
+    pub order_number: i32,
+    pub user_name: String,
+    pub user_email: String,
+    pub currency: String,
+    /** In cents */
+    pub total: i64,
+    pub total_formatted: String,
+    /** `pending`, `failed`, `paid` or `refunded` */
+    pub status: String,
+    pub status_formatted: String,
+    pub refunded: bool,
+    #[serde(default)]
+    pub first_order_item: Option<OrderFirstItem>,
+    #[serde(default)]
+    pub urls: Option<OrderUrls>,
+    pub created_at: String,
+}
+
+/** The purchased variant, orders of this app have a single item */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderFirstItem {
+    pub product_id: i32,
+    pub variant_id: i32,
+    pub product_name: String,
+    pub variant_name: String,
+    /** In cents */
+    pub price: i64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderUrls {
+    pub receipt: Option<String>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SubscriptionInvoiceObject {
     pub r#type: String,
@@ -256,6 +572,277 @@ pub struct SubscriptionInvoiceObjectAttributes {
     pub customer_id: i32,
     pub user_name: String,
     pub user_email: String,
+    /** `initial`, `renewal` or `updated` */
+    pub billing_reason: String,
+    pub currency: String,
+    /** In cents */
+    pub total: i64,
+    pub total_formatted: String,
+    /** `pending`, `paid`, `void`, `refunded` or `partial_refund` */
     pub status: String,
     pub status_formatted: String,
+    pub refunded: bool,
+    #[serde(default)]
+    pub urls: Option<SubscriptionInvoiceUrls>,
+    pub created_at: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubscriptionInvoiceUrls {
+    pub invoice_url: Option<String>,
+}
+
+pub type GetSubscriptionInvoicesParams = ListParams<GetSubscriptionInvoicesFilter>;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct GetSubscriptionInvoicesFilter {
+    pub store_id: Option<i32>,
+    pub status: Option<String>,
+    pub refunded: Option<bool>,
+    pub subscription_id: Option<i32>,
+}
+
+pub type GetSubscriptionInvoicesResponse = ListResponse<SubscriptionInvoiceObject>;
+
+pub type GetOrdersParams = ListParams<GetOrdersFilter>;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct GetOrdersFilter {
+    pub store_id: Option<i32>,
+    pub user_email: Option<String>,
+}
+
+pub type GetOrdersResponse = ListResponse<OrderObject>;
+
+impl LemonSqueezy {
+    /** Get subscription invoice by id */
+    pub async fn get_subscription_invoice(
+        &self,
+        invoice_id: i32,
+    ) -> Result<SubscriptionInvoiceObject, LemonSqueezyError> {
+        self.retrieve("/subscription-invoices", invoice_id).await
+    }
+
+    /** Get a page of subscription invoices by conditions */
+    pub async fn get_subscription_invoices(
+        &self,
+        params: GetSubscriptionInvoicesParams,
+    ) -> Result<GetSubscriptionInvoicesResponse, LemonSqueezyError> {
+        self.list("/subscription-invoices", params).await
+    }
+
+    /** Walk all subscription invoices matching the conditions */
+    pub fn paginate_subscription_invoices(
+        &self,
+        params: GetSubscriptionInvoicesParams,
+    ) -> Paginator<'_, SubscriptionInvoiceObject, GetSubscriptionInvoicesFilter> {
+        Paginator::new(self, "/subscription-invoices", params)
+    }
+
+    /** Get order by id */
+    pub async fn get_order(&self, order_id: i32) -> Result<OrderObject, LemonSqueezyError> {
+        self.retrieve("/orders", order_id).await
+    }
+
+    /** Get a page of orders by conditions */
+    pub async fn get_orders(
+        &self,
+        params: GetOrdersParams,
+    ) -> Result<GetOrdersResponse, LemonSqueezyError> {
+        self.list("/orders", params).await
+    }
+
+    /** Walk all orders matching the conditions */
+    pub fn paginate_orders(
+        &self,
+        params: GetOrdersParams,
+    ) -> Paginator<'_, OrderObject, GetOrdersFilter> {
+        Paginator::new(self, "/orders", params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+    };
+
+    use super::*;
+
+    /**
+     * Serves the responses in order, one per connection, and reports each request head.
+     * The responses are built with the base URL of the server, e.g. for pagination links.
+     */
+    fn mock_server(
+        responses: impl FnOnce(&str) -> Vec<String>,
+    ) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let responses = responses(&base_url);
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = String::new();
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                    head.push_str(&line);
+                }
+                sender.send(head).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (base_url, receiver)
+    }
+
+    fn response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/vnd.api+json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            headers,
+            body.len(),
+            body
+        )
+    }
+
+    fn store_json(id: u32) -> String {
+        format!(
+            r#"{{"type": "stores", "id": "{}", "attributes": {{"name": "Store", "slug": "store", "domain": "store.lemonsqueezy.com", "url": "https://store.lemonsqueezy.com", "currency": "USD", "created_at": "2024-01-01T00:00:00.000000Z"}}}}"#,
+            id
+        )
+    }
+
+    fn client(base_url: &str) -> LemonSqueezy {
+        LemonSqueezy::builder(String::from("test-key"))
+            .base_url(base_url)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn get_store_sends_key_and_decodes_data() {
+        let body = format!(r#"{{"data": {}}}"#, store_json(1));
+        let (base_url, requests) = mock_server(|_| vec![response("200 OK", "", &body)]);
+
+        let store = client(&base_url).get_store(1).await.unwrap();
+
+        assert_eq!(store.id, "1");
+        assert_eq!(store.attributes.currency, "USD");
+        let head = requests.recv().unwrap();
+        assert!(head.starts_with("GET /v1/stores/1 HTTP/1.1"));
+        assert!(head
+            .to_lowercase()
+            .contains("authorization: bearer test-key"));
+    }
+
+    #[tokio::test]
+    async fn rate_limited_response_has_retry_after() {
+        let (base_url, _requests) =
+            mock_server(|_| vec![response("429 Too Many Requests", "Retry-After: 7\r\n", "")]);
+
+        let err = client(&base_url).get_store(1).await.unwrap_err();
+
+        assert!(matches!(
+            err,
+            LemonSqueezyError::RateLimited {
+                retry_after: Some(7)
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn error_response_keeps_api_errors() {
+        let body = r#"{"errors": [{"status": "404", "title": "Not Found", "detail": "The store does not exist."}]}"#;
+        let (base_url, _requests) = mock_server(|_| vec![response("404 Not Found", "", body)]);
+
+        let err = client(&base_url).get_store(1).await.unwrap_err();
+
+        match err {
+            LemonSqueezyError::NotFound(errors) => {
+                assert_eq!(
+                    errors[0].detail.as_deref(),
+                    Some("The store does not exist.")
+                );
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn paginator_follows_next_links() {
+        let (base_url, requests) = mock_server(|base_url| {
+            let first_page = format!(
+                r#"{{"meta": {{"page": {{"currentPage": 1, "lastPage": 2, "perPage": 1, "total": 2}}}}, "links": {{"next": "{}/stores?page%5Bnumber%5D=2&page%5Bsize%5D=1&cursor=abc"}}, "data": [{}]}}"#,
+                base_url,
+                store_json(1)
+            );
+            let second_page = format!(
+                r#"{{"meta": {{"page": {{"currentPage": 2, "lastPage": 2, "perPage": 1, "total": 2}}}}, "links": {{"next": null}}, "data": [{}]}}"#,
+                store_json(2)
+            );
+            vec![
+                response("200 OK", "", &first_page),
+                response("200 OK", "", &second_page),
+            ]
+        });
+
+        let stores = client(&base_url)
+            .paginate_stores(GetStoresParams {
+                page: PageParams { number: 1, size: 1 },
+                ..Default::default()
+            })
+            .all()
+            .await
+            .unwrap();
+
+        let ids: Vec<&str> = stores.iter().map(|store| store.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "2"]);
+        assert!(requests
+            .recv()
+            .unwrap()
+            .starts_with("GET /v1/stores?page[number]=1&page[size]=1 "));
+        assert!(requests.recv().unwrap().contains("cursor=abc"));
+    }
+
+    #[tokio::test]
+    async fn paginator_ignores_next_links_of_other_hosts() {
+        let (base_url, requests) = mock_server(|_| {
+            let first_page = format!(
+                r#"{{"meta": {{"page": {{"currentPage": 1, "lastPage": 2, "perPage": 1, "total": 2}}}}, "links": {{"next": "https://example.com/stores?page%5Bnumber%5D=2"}}, "data": [{}]}}"#,
+                store_json(1)
+            );
+            let second_page = format!(
+                r#"{{"meta": {{"page": {{"currentPage": 2, "lastPage": 2, "perPage": 1, "total": 2}}}}, "data": [{}]}}"#,
+                store_json(2)
+            );
+            vec![
+                response("200 OK", "", &first_page),
+                response("200 OK", "", &second_page),
+            ]
+        });
+
+        let stores = client(&base_url)
+            .paginate_stores(GetStoresParams {
+                page: PageParams { number: 1, size: 1 },
+                ..Default::default()
+            })
+            .all()
+            .await
+            .unwrap();
+
+        assert_eq!(stores.len(), 2);
+        requests.recv().unwrap();
+        assert!(requests
+            .recv()
+            .unwrap()
+            .starts_with("GET /v1/stores?page[number]=2&page[size]=1 "));
+    }
 }
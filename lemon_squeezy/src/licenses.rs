@@ -0,0 +1,173 @@
+use reqwest::header::{HeaderValue, ACCEPT};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{LemonSqueezy, LemonSqueezyError};
+
+/** The license key as seen by the License API */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LicenseKeyInfo {
+    pub id: i32,
+    /** `inactive`, `active`, `expired` or `disabled` */
+    pub status: String,
+    pub key: String,
+    /** `None` for unlimited activations */
+    pub activation_limit: Option<i32>,
+    pub activation_usage: i32,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+}
+
+/** An activation of the license key, e.g. one installation of the app */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LicenseInstance {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LicenseMeta {
+    pub store_id: i32,
+    pub order_id: i32,
+    pub order_item_id: i32,
+    pub product_id: i32,
+    pub product_name: String,
+    pub variant_id: i32,
+    pub variant_name: String,
+    pub customer_id: i32,
+    pub customer_name: String,
+    pub customer_email: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ActivateLicenseResponse {
+    pub activated: bool,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub license_key: Option<LicenseKeyInfo>,
+    #[serde(default)]
+    pub instance: Option<LicenseInstance>,
+    #[serde(default)]
+    pub meta: Option<LicenseMeta>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ValidateLicenseResponse {
+    pub valid: bool,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub license_key: Option<LicenseKeyInfo>,
+    #[serde(default)]
+    pub instance: Option<LicenseInstance>,
+    #[serde(default)]
+    pub meta: Option<LicenseMeta>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeactivateLicenseResponse {
+    pub deactivated: bool,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub license_key: Option<LicenseKeyInfo>,
+    #[serde(default)]
+    pub meta: Option<LicenseMeta>,
+}
+
+#[derive(Serialize)]
+struct LicenseParams<'a> {
+    license_key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance_name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance_id: Option<&'a str>,
+}
+
+impl LemonSqueezy {
+    /**
+     * The License API takes form data and answers plain JSON, not JSON:API. A rejected key is
+     * reported in the body with `error`, so those responses are returned instead of an error.
+     */
+    async fn send_license_request<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        params: &LicenseParams<'_>,
+    ) -> Result<T, LemonSqueezyError> {
+        let mut headers = self.headers.clone();
+        headers.remove(reqwest::header::CONTENT_TYPE);
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+
+        let response = self
+            .client
+            .post(self.url(path))
+            .headers(headers)
+            .form(params)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let retry_after = Self::retry_after(&response);
+        let body = response.text().await?;
+
+        if status.is_client_error() {
+            if let Ok(response) = serde_json::from_str::<T>(&body) {
+                return Ok(response);
+            }
+        }
+        if !status.is_success() {
+            return Err(LemonSqueezyError::from_response(status, retry_after, &body));
+        }
+
+        Ok(serde_json::from_str::<T>(&body)?)
+    }
+
+    /** Activate the license key, creating an instance with the given name */
+    pub async fn activate_license(
+        &self,
+        license_key: &str,
+        instance_name: &str,
+    ) -> Result<ActivateLicenseResponse, LemonSqueezyError> {
+        self.send_license_request(
+            "/licenses/activate",
+            &LicenseParams {
+                license_key,
+                instance_name: Some(instance_name),
+                instance_id: None,
+            },
+        )
+        .await
+    }
+
+    /** Validate the license key, and that the instance belongs to it when given */
+    pub async fn validate_license(
+        &self,
+        license_key: &str,
+        instance_id: Option<&str>,
+    ) -> Result<ValidateLicenseResponse, LemonSqueezyError> {
+        self.send_license_request(
+            "/licenses/validate",
+            &LicenseParams {
+                license_key,
+                instance_name: None,
+                instance_id,
+            },
+        )
+        .await
+    }
+
+    /** Deactivate the instance, freeing an activation of the license key */
+    pub async fn deactivate_license(
+        &self,
+        license_key: &str,
+        instance_id: &str,
+    ) -> Result<DeactivateLicenseResponse, LemonSqueezyError> {
+        self.send_license_request(
+            "/licenses/deactivate",
+            &LicenseParams {
+                license_key,
+                instance_name: None,
+                instance_id: Some(instance_id),
+            },
+        )
+        .await
+    }
+}
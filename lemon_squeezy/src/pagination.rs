@@ -0,0 +1,214 @@
+use std::marker::PhantomData;
+
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{LemonSqueezy, LemonSqueezyError};
+
+/** Serialized as `filter[store_id]=1&page[number]=1` */
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct ListParams<F> {
+    pub filter: F,
+    pub page: PageParams,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PageParams {
+    pub number: u32,
+    pub size: u32,
+}
+
+impl Default for PageParams {
+    fn default() -> Self {
+        Self {
+            number: 1,
+            size: 10,
+        }
+    }
+}
+
+/** A page of a JSON:API collection */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ListResponse<T> {
+    pub meta: ListMeta,
+    #[serde(default)]
+    pub links: Option<ListLinks>,
+    pub data: Vec<T>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ListMeta {
+    pub page: PageMeta,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageMeta {
+    pub current_page: u32,
+    pub last_page: u32,
+    pub per_page: u32,
+    pub total: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ListLinks {
+    pub first: Option<String>,
+    pub last: Option<String>,
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct DataResponse<T> {
+    pub data: T,
+}
+
+/**
+ * Whether `url` is below `base_url`. The API key is sent along, so the link of a page must not
+ * lead to another host, port or API.
+ */
+fn is_api_url(base_url: &str, url: &str) -> bool {
+    let (Ok(base_url), Ok(url)) = (Url::parse(base_url), Url::parse(url)) else {
+        return false;
+    };
+    let base_path = base_url.path().trim_end_matches('/');
+
+    url.scheme() == base_url.scheme()
+        && url.host() == base_url.host()
+        && url.port_or_known_default() == base_url.port_or_known_default()
+        && (url.path() == base_path || url.path().starts_with(&format!("{}/", base_path)))
+}
+
+/**
+ * Walks a collection page by page. Follows `links.next` of each page, or requests the next
+ * `page[number]` from `meta.page` when the link is missing or points to another host.
+ */
+#[derive(Debug)]
+pub struct Paginator<'a, T, F> {
+    client: &'a LemonSqueezy,
+    path: &'static str,
+    params: ListParams<F>,
+    next_url: Option<String>,
+    finished: bool,
+    _data: PhantomData<T>,
+}
+
+impl<'a, T: DeserializeOwned, F: Serialize> Paginator<'a, T, F> {
+    pub(crate) fn new(client: &'a LemonSqueezy, path: &'static str, params: ListParams<F>) -> Self {
+        Self {
+            client,
+            path,
+            params,
+            next_url: None,
+            finished: false,
+            _data: PhantomData,
+        }
+    }
+
+    /** Fetch the next page, `None` after the last page */
+    pub async fn next_page(&mut self) -> Result<Option<Vec<T>>, LemonSqueezyError> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let url = match self.next_url.take() {
+            Some(url) => url,
+            None => self.client.list_url(self.path, &self.params)?,
+        };
+        let response: ListResponse<T> = self.client.send(self.client.client.get(url)).await?;
+
+        let page = &response.meta.page;
+        if page.current_page >= page.last_page || response.data.is_empty() {
+            self.finished = true;
+        } else {
+            self.params.page.number = page.current_page + 1;
+            self.next_url = response
+                .links
+                .and_then(|links| links.next)
+                .filter(|next| is_api_url(&self.client.base_url, next));
+        }
+
+        Ok(Some(response.data))
+    }
+
+    /** Fetch all remaining pages */
+    pub async fn all(mut self) -> Result<Vec<T>, LemonSqueezyError> {
+        let mut items = vec![];
+
+        while let Some(page) = self.next_page().await? {
+            items.extend(page);
+        }
+
+        Ok(items)
+    }
+}
+
+impl LemonSqueezy {
+    fn list_url<F: Serialize>(
+        &self,
+        path: &str,
+        params: &ListParams<F>,
+    ) -> Result<String, LemonSqueezyError> {
+        Ok(self.url(&format!("{}?{}", path, Self::query(params)?)))
+    }
+
+    /** Get a single page of the collection */
+    pub(crate) async fn list<T: DeserializeOwned, F: Serialize>(
+        &self,
+        path: &'static str,
+        params: ListParams<F>,
+    ) -> Result<ListResponse<T>, LemonSqueezyError> {
+        let url = self.list_url(path, &params)?;
+
+        self.send(self.client.get(url)).await
+    }
+
+    /** Get a single resource */
+    pub(crate) async fn retrieve<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        id: i32,
+    ) -> Result<T, LemonSqueezyError> {
+        let url = self.url(&format!("{}/{}", path, id));
+        let response: DataResponse<T> = self.send(self.client.get(url)).await?;
+
+        Ok(response.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_api_url_accepts_urls_below_base_url() {
+        let base_url = "http://127.0.0.1:1234/v1";
+
+        assert!(is_api_url(
+            base_url,
+            "http://127.0.0.1:1234/v1/orders?page[number]=2"
+        ));
+        assert!(is_api_url(base_url, "http://127.0.0.1:1234/v1?page=2"));
+        assert!(is_api_url(
+            "https://api.lemonsqueezy.com",
+            "https://api.lemonsqueezy.com:443/v1/orders?page[number]=2"
+        ));
+    }
+
+    #[test]
+    fn is_api_url_rejects_other_origins() {
+        let base_url = "http://127.0.0.1:1234/v1";
+
+        // a string prefix of the base URL
+        assert!(!is_api_url(base_url, "http://127.0.0.1:12345/v1/orders"));
+        assert!(!is_api_url(base_url, "http://127.0.0.1:1234/v10/orders"));
+        assert!(!is_api_url(
+            "https://api.lemonsqueezy.com",
+            "https://api.lemonsqueezy.com.evil.test/v1/orders"
+        ));
+        assert!(!is_api_url(base_url, "https://127.0.0.1:1234/v1/orders"));
+        assert!(!is_api_url(base_url, "http://localhost:1234/v1/orders"));
+        assert!(!is_api_url(base_url, "http://127.0.0.1:1234/v2/orders"));
+        assert!(!is_api_url(base_url, "/v1/orders"));
+    }
+}
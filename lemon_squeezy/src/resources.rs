@@ -0,0 +1,505 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    pagination::DataResponse, LemonSqueezy, LemonSqueezyError, ListParams, ListResponse, Paginator,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoreObject {
+    pub r#type: String,
+    pub id: String,
+    pub attributes: StoreObjectAttributes,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoreObjectAttributes {
+    pub name: String,
+    pub slug: String,
+    pub domain: String,
+    pub url: String,
+    pub currency: String,
+    pub created_at: String,
+}
+
+/** Stores have no filters */
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct GetStoresFilter {}
+
+pub type GetStoresParams = ListParams<GetStoresFilter>;
+
+pub type GetStoresResponse = ListResponse<StoreObject>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProductObject {
+    pub r#type: String,
+    pub id: String,
+    pub attributes: ProductObjectAttributes,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProductObjectAttributes {
+    pub store_id: i32,
+    pub name: String,
+    pub slug: String,
+    pub description: Option<String>,
+    /** `draft` or `published` */
+    pub status: String,
+    /** In cents */
+    pub price: i64,
+    pub price_formatted: String,
+    pub buy_now_url: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct GetProductsFilter {
+    pub store_id: Option<i32>,
+}
+
+pub type GetProductsParams = ListParams<GetProductsFilter>;
+
+pub type GetProductsResponse = ListResponse<ProductObject>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VariantObject {
+    pub r#type: String,
+    pub id: String,
+    pub attributes: VariantObjectAttributes,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VariantObjectAttributes {
+    pub product_id: i32,
+    pub name: String,
+    pub slug: String,
+    pub description: Option<String>,
+    /** In cents */
+    pub price: i64,
+    pub is_subscription: bool,
+    /** `day`, `week`, `month` or `year` for subscriptions */
+    pub interval: Option<String>,
+    pub interval_count: Option<i32>,
+    pub has_free_trial: bool,
+    pub trial_interval: Option<String>,
+    pub trial_interval_count: Option<i32>,
+    /** `pending`, `draft` or `published` */
+    pub status: String,
+    pub created_at: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct GetVariantsFilter {
+    pub product_id: Option<i32>,
+    pub status: Option<String>,
+}
+
+pub type GetVariantsParams = ListParams<GetVariantsFilter>;
+
+pub type GetVariantsResponse = ListResponse<VariantObject>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CustomerObject {
+    pub r#type: String,
+    pub id: String,
+    pub attributes: CustomerObjectAttributes,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CustomerObjectAttributes {
+    pub store_id: i32,
+    pub name: String,
+    pub email: String,
+    /** `subscribed`, `unsubscribed`, `archived`, `requires_verification` or `invalid_email` */
+    pub status: String,
+    pub city: Option<String>,
+    pub region: Option<String>,
+    pub country: Option<String>,
+    /** In cents */
+    pub total_revenue_currency: i64,
+    /** In cents */
+    pub mrr: i64,
+    #[serde(default)]
+    pub urls: Option<CustomerUrls>,
+    pub created_at: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CustomerUrls {
+    pub customer_portal: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct GetCustomersFilter {
+    pub store_id: Option<i32>,
+    pub email: Option<String>,
+}
+
+pub type GetCustomersParams = ListParams<GetCustomersFilter>;
+
+pub type GetCustomersResponse = ListResponse<CustomerObject>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderItemObject {
+    pub r#type: String,
+    pub id: String,
+    pub attributes: OrderItemObjectAttributes,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderItemObjectAttributes {
+    pub order_id: i32,
+    pub product_id: i32,
+    pub variant_id: i32,
+    pub product_name: String,
+    pub variant_name: String,
+    /** In cents */
+    pub price: i64,
+    pub quantity: i32,
+    pub created_at: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct GetOrderItemsFilter {
+    pub order_id: Option<i32>,
+    pub product_id: Option<i32>,
+    pub variant_id: Option<i32>,
+}
+
+pub type GetOrderItemsParams = ListParams<GetOrderItemsFilter>;
+
+pub type GetOrderItemsResponse = ListResponse<OrderItemObject>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiscountObject {
+    pub r#type: String,
+    pub id: String,
+    pub attributes: DiscountObjectAttributes,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiscountObjectAttributes {
+    pub store_id: i32,
+    pub name: String,
+    pub code: String,
+    /** Percent, or cents for fixed discounts */
+    pub amount: i64,
+    /** `percent` or `fixed` */
+    pub amount_type: String,
+    pub is_limited_to_products: bool,
+    pub is_limited_redemptions: bool,
+    pub max_redemptions: i32,
+    pub starts_at: Option<String>,
+    pub expires_at: Option<String>,
+    /** `once`, `repeating` or `forever` */
+    pub duration: String,
+    pub duration_in_months: i32,
+    /** `draft` or `published` */
+    pub status: String,
+    pub created_at: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct GetDiscountsFilter {
+    pub store_id: Option<i32>,
+}
+
+pub type GetDiscountsParams = ListParams<GetDiscountsFilter>;
+
+pub type GetDiscountsResponse = ListResponse<DiscountObject>;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct CreateDiscountParams {
+    pub store_id: i32,
+    pub name: String,
+    /** 3 to 256 uppercase letters and numbers */
+    pub code: String,
+    /** Percent, or cents for fixed discounts */
+    pub amount: i64,
+    /** `percent` or `fixed` */
+    pub amount_type: String,
+    /** `once`, `repeating` or `forever`, for subscriptions */
+    pub duration: Option<String>,
+    pub duration_in_months: Option<i32>,
+    /** Total number of redemptions, unlimited when `None` */
+    pub max_redemptions: Option<i32>,
+    pub starts_at: Option<String>,
+    pub expires_at: Option<String>,
+    /** Only applies to these variants, all products of the store when empty */
+    pub variant_ids: Vec<i32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LicenseKeyObject {
+    pub r#type: String,
+    pub id: String,
+    pub attributes: LicenseKeyObjectAttributes,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LicenseKeyObjectAttributes {
+    pub store_id: i32,
+    pub customer_id: i32,
+    pub order_id: i32,
+    pub order_item_id: i32,
+    pub product_id: i32,
+    pub user_name: String,
+    pub user_email: String,
+    pub key: String,
+    pub key_short: String,
+    /** `None` for unlimited activations */
+    pub activation_limit: Option<i32>,
+    pub instances_count: i32,
+    pub disabled: bool,
+    /** `inactive`, `active`, `expired` or `disabled` */
+    pub status: String,
+    pub expires_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct GetLicenseKeysFilter {
+    pub store_id: Option<i32>,
+    pub order_id: Option<i32>,
+    pub order_item_id: Option<i32>,
+    pub product_id: Option<i32>,
+    pub status: Option<String>,
+}
+
+pub type GetLicenseKeysParams = ListParams<GetLicenseKeysFilter>;
+
+pub type GetLicenseKeysResponse = ListResponse<LicenseKeyObject>;
+
+impl LemonSqueezy {
+    /** Get store by id */
+    pub async fn get_store(&self, store_id: i32) -> Result<StoreObject, LemonSqueezyError> {
+        self.retrieve("/stores", store_id).await
+    }
+
+    /** Get a page of stores */
+    pub async fn get_stores(
+        &self,
+        params: GetStoresParams,
+    ) -> Result<GetStoresResponse, LemonSqueezyError> {
+        self.list("/stores", params).await
+    }
+
+    /** Walk all stores */
+    pub fn paginate_stores(
+        &self,
+        params: GetStoresParams,
+    ) -> Paginator<'_, StoreObject, GetStoresFilter> {
+        Paginator::new(self, "/stores", params)
+    }
+
+    /** Get product by id */
+    pub async fn get_product(&self, product_id: i32) -> Result<ProductObject, LemonSqueezyError> {
+        self.retrieve("/products", product_id).await
+    }
+
+    /** Get a page of products by conditions */
+    pub async fn get_products(
+        &self,
+        params: GetProductsParams,
+    ) -> Result<GetProductsResponse, LemonSqueezyError> {
+        self.list("/products", params).await
+    }
+
+    /** Walk all products matching the conditions */
+    pub fn paginate_products(
+        &self,
+        params: GetProductsParams,
+    ) -> Paginator<'_, ProductObject, GetProductsFilter> {
+        Paginator::new(self, "/products", params)
+    }
+
+    /** Get variant by id */
+    pub async fn get_variant(&self, variant_id: i32) -> Result<VariantObject, LemonSqueezyError> {
+        self.retrieve("/variants", variant_id).await
+    }
+
+    /** Get a page of variants by conditions */
+    pub async fn get_variants(
+        &self,
+        params: GetVariantsParams,
+    ) -> Result<GetVariantsResponse, LemonSqueezyError> {
+        self.list("/variants", params).await
+    }
+
+    /** Walk all variants matching the conditions */
+    pub fn paginate_variants(
+        &self,
+        params: GetVariantsParams,
+    ) -> Paginator<'_, VariantObject, GetVariantsFilter> {
+        Paginator::new(self, "/variants", params)
+    }
+
+    /** Get customer by id */
+    pub async fn get_customer(
+        &self,
+        customer_id: i32,
+    ) -> Result<CustomerObject, LemonSqueezyError> {
+        self.retrieve("/customers", customer_id).await
+    }
+
+    /** Get a page of customers by conditions */
+    pub async fn get_customers(
+        &self,
+        params: GetCustomersParams,
+    ) -> Result<GetCustomersResponse, LemonSqueezyError> {
+        self.list("/customers", params).await
+    }
+
+    /** Walk all customers matching the conditions */
+    pub fn paginate_customers(
+        &self,
+        params: GetCustomersParams,
+    ) -> Paginator<'_, CustomerObject, GetCustomersFilter> {
+        Paginator::new(self, "/customers", params)
+    }
+
+    /** Get order item by id */
+    pub async fn get_order_item(
+        &self,
+        order_item_id: i32,
+    ) -> Result<OrderItemObject, LemonSqueezyError> {
+        self.retrieve("/order-items", order_item_id).await
+    }
+
+    /** Get a page of order items by conditions */
+    pub async fn get_order_items(
+        &self,
+        params: GetOrderItemsParams,
+    ) -> Result<GetOrderItemsResponse, LemonSqueezyError> {
+        self.list("/order-items", params).await
+    }
+
+    /** Walk all order items matching the conditions */
+    pub fn paginate_order_items(
+        &self,
+        params: GetOrderItemsParams,
+    ) -> Paginator<'_, OrderItemObject, GetOrderItemsFilter> {
+        Paginator::new(self, "/order-items", params)
+    }
+
+    /** Get discount by id */
+    pub async fn get_discount(
+        &self,
+        discount_id: i32,
+    ) -> Result<DiscountObject, LemonSqueezyError> {
+        self.retrieve("/discounts", discount_id).await
+    }
+
+    /** Get a page of discounts by conditions */
+    pub async fn get_discounts(
+        &self,
+        params: GetDiscountsParams,
+    ) -> Result<GetDiscountsResponse, LemonSqueezyError> {
+        self.list("/discounts", params).await
+    }
+
+    /** Create a discount code */
+    pub async fn create_discount(
+        &self,
+        params: CreateDiscountParams,
+    ) -> Result<DiscountObject, LemonSqueezyError> {
+        let url = self.url("/discounts");
+
+        let mut relationships = json!({
+            "store": {
+                "data": {
+                    "type": "stores",
+                    "id": format!("{}", params.store_id),
+                }
+            }
+        });
+        if !params.variant_ids.is_empty() {
+            relationships["variants"] = json!({
+                "data": params
+                    .variant_ids
+                    .iter()
+                    .map(|variant_id| json!({
+                        "type": "variants",
+                        "id": format!("{}", variant_id),
+                    }))
+                    .collect::<Vec<_>>(),
+            });
+        }
+
+        let response: DataResponse<DiscountObject> = self
+            .send(self.client.post(url).json(&json!({
+                "data": {
+                    "type": "discounts",
+                    "attributes": {
+                        "name": params.name,
+                        "code": params.code,
+                        "amount": params.amount,
+                        "amount_type": params.amount_type,
+                        "duration": params.duration,
+                        "duration_in_months": params.duration_in_months,
+                        "is_limited_to_products": !params.variant_ids.is_empty(),
+                        "is_limited_redemptions": params.max_redemptions.is_some(),
+                        "max_redemptions": params.max_redemptions,
+                        "starts_at": params.starts_at,
+                        "expires_at": params.expires_at,
+                    },
+                    "relationships": relationships,
+                }
+            })))
+            .await?;
+
+        Ok(response.data)
+    }
+
+    /** Delete a discount code, e.g. one that was not redeemed in time */
+    pub async fn delete_discount(&self, discount_id: i32) -> Result<(), LemonSqueezyError> {
+        let url = self.url(&format!("/discounts/{}", discount_id));
+        let response = self
+            .client
+            .delete(url)
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let retry_after = Self::retry_after(&response);
+            let body = response.text().await?;
+            return Err(LemonSqueezyError::from_response(status, retry_after, &body));
+        }
+
+        Ok(())
+    }
+
+    /** Walk all discounts matching the conditions */
+    pub fn paginate_discounts(
+        &self,
+        params: GetDiscountsParams,
+    ) -> Paginator<'_, DiscountObject, GetDiscountsFilter> {
+        Paginator::new(self, "/discounts", params)
+    }
+
+    /** Get license key by id */
+    pub async fn get_license_key(
+        &self,
+        license_key_id: i32,
+    ) -> Result<LicenseKeyObject, LemonSqueezyError> {
+        self.retrieve("/license-keys", license_key_id).await
+    }
+
+    /** Get a page of license keys by conditions */
+    pub async fn get_license_keys(
+        &self,
+        params: GetLicenseKeysParams,
+    ) -> Result<GetLicenseKeysResponse, LemonSqueezyError> {
+        self.list("/license-keys", params).await
+    }
+
+    /** Walk all license keys matching the conditions */
+    pub fn paginate_license_keys(
+        &self,
+        params: GetLicenseKeysParams,
+    ) -> Paginator<'_, LicenseKeyObject, GetLicenseKeysFilter> {
+        Paginator::new(self, "/license-keys", params)
+    }
+}
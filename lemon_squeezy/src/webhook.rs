@@ -0,0 +1,237 @@
+use ring::hmac;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{LicenseKeyObject, OrderObject, SubscriptionInvoiceObject, SubscriptionObject};
+
+/**
+ * Check the `X-Signature` header, the hex encoded HMAC-SHA256 of the raw body with the signing
+ * secret of the webhook. The comparison runs in constant time.
+ */
+pub fn verify_signature(secret: &str, payload: &[u8], signature: &str) -> bool {
+    let Some(signature) = decode_hex(signature.trim()) else {
+        return false;
+    };
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+
+    hmac::verify(&key, payload, &signature).is_ok()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    // `from_str_radix` alone would also accept a sign like `+f`
+    if value.len() % 2 != 0 || !value.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "C: Deserialize<'de> + Default"))]
+pub struct WebhookMeta<C> {
+    pub event_name: String,
+    #[serde(default)]
+    pub test_mode: bool,
+    /** `checkout_data.custom` of the checkout, missing when the checkout had none */
+    #[serde(default)]
+    pub custom_data: C,
+}
+
+/** The payload with `data` left untyped, e.g. to store the event before processing it */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "C: Deserialize<'de> + Default"))]
+pub struct WebhookEnvelope<C = serde_json::Value> {
+    pub meta: WebhookMeta<C>,
+    pub data: serde_json::Value,
+}
+
+/** A webhook payload whose `data` is decoded by `meta.event_name` */
+#[derive(Clone, Debug, Deserialize)]
+#[serde(
+    try_from = "WebhookEnvelope<C>",
+    bound(deserialize = "C: DeserializeOwned + Default")
+)]
+pub struct WebhookPayload<C = serde_json::Value> {
+    pub meta: WebhookMeta<C>,
+    pub event: WebhookEvent,
+}
+
+#[derive(Clone, Debug)]
+pub enum WebhookEvent {
+    OrderCreated(OrderObject),
+    OrderRefunded(OrderObject),
+    SubscriptionCreated(SubscriptionObject),
+    SubscriptionUpdated(SubscriptionObject),
+    SubscriptionCancelled(SubscriptionObject),
+    SubscriptionResumed(SubscriptionObject),
+    SubscriptionExpired(SubscriptionObject),
+    SubscriptionPaused(SubscriptionObject),
+    SubscriptionUnpaused(SubscriptionObject),
+    SubscriptionPaymentSuccess(SubscriptionInvoiceObject),
+    SubscriptionPaymentFailed(SubscriptionInvoiceObject),
+    SubscriptionPaymentRecovered(SubscriptionInvoiceObject),
+    SubscriptionPaymentRefunded(SubscriptionInvoiceObject),
+    LicenseKeyCreated(LicenseKeyObject),
+    LicenseKeyUpdated(LicenseKeyObject),
+    /** Events this crate does not know yet */
+    Unknown {
+        event_name: String,
+        data: serde_json::Value,
+    },
+}
+
+impl WebhookEvent {
+    fn from_data(event_name: &str, data: serde_json::Value) -> Result<Self, serde_json::Error> {
+        let event = match event_name {
+            "order_created" => WebhookEvent::OrderCreated(serde_json::from_value(data)?),
+            "order_refunded" => WebhookEvent::OrderRefunded(serde_json::from_value(data)?),
+            "subscription_created" => {
+                WebhookEvent::SubscriptionCreated(serde_json::from_value(data)?)
+            }
+            "subscription_updated" => {
+                WebhookEvent::SubscriptionUpdated(serde_json::from_value(data)?)
+            }
+            "subscription_cancelled" => {
+                WebhookEvent::SubscriptionCancelled(serde_json::from_value(data)?)
+            }
+            "subscription_resumed" => {
+                WebhookEvent::SubscriptionResumed(serde_json::from_value(data)?)
+            }
+            "subscription_expired" => {
+                WebhookEvent::SubscriptionExpired(serde_json::from_value(data)?)
+            }
+            "subscription_paused" => {
+                WebhookEvent::SubscriptionPaused(serde_json::from_value(data)?)
+            }
+            "subscription_unpaused" => {
+                WebhookEvent::SubscriptionUnpaused(serde_json::from_value(data)?)
+            }
+            "subscription_payment_success" => {
+                WebhookEvent::SubscriptionPaymentSuccess(serde_json::from_value(data)?)
+            }
+            "subscription_payment_failed" => {
+                WebhookEvent::SubscriptionPaymentFailed(serde_json::from_value(data)?)
+            }
+            "subscription_payment_recovered" => {
+                WebhookEvent::SubscriptionPaymentRecovered(serde_json::from_value(data)?)
+            }
+            "subscription_payment_refunded" => {
+                WebhookEvent::SubscriptionPaymentRefunded(serde_json::from_value(data)?)
+            }
+            "license_key_created" => WebhookEvent::LicenseKeyCreated(serde_json::from_value(data)?),
+            "license_key_updated" => WebhookEvent::LicenseKeyUpdated(serde_json::from_value(data)?),
+            _ => WebhookEvent::Unknown {
+                event_name: event_name.to_owned(),
+                data,
+            },
+        };
+
+        Ok(event)
+    }
+}
+
+impl<C> TryFrom<WebhookEnvelope<C>> for WebhookPayload<C> {
+    type Error = serde_json::Error;
+
+    fn try_from(envelope: WebhookEnvelope<C>) -> Result<Self, Self::Error> {
+        let event = WebhookEvent::from_data(&envelope.meta.event_name, envelope.data)?;
+
+        Ok(WebhookPayload {
+            meta: envelope.meta,
+            event,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "key";
+    const BODY: &[u8] = b"The quick brown fox jumps over the lazy dog";
+    /** HMAC-SHA256 of `BODY` with `SECRET` */
+    const SIGNATURE: &str = "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8";
+
+    #[test]
+    fn verify_signature_accepts_valid_signature() {
+        assert!(verify_signature(SECRET, BODY, SIGNATURE));
+        assert!(verify_signature(SECRET, BODY, &SIGNATURE.to_uppercase()));
+        assert!(verify_signature(SECRET, BODY, &format!(" {}\n", SIGNATURE)));
+    }
+
+    #[test]
+    fn verify_signature_rejects_tampered_body() {
+        assert!(!verify_signature(
+            SECRET,
+            b"The quick brown fox jumps over the lazy cog",
+            SIGNATURE
+        ));
+        assert!(!verify_signature(SECRET, b"", SIGNATURE));
+    }
+
+    #[test]
+    fn verify_signature_rejects_wrong_secret() {
+        assert!(!verify_signature("another key", BODY, SIGNATURE));
+    }
+
+    #[test]
+    fn verify_signature_rejects_malformed_signature() {
+        // odd length
+        assert!(!verify_signature(SECRET, BODY, &SIGNATURE[1..]));
+        // not hex
+        assert!(!verify_signature(SECRET, BODY, &"zz".repeat(32)));
+        // multi-byte characters must not panic on slicing
+        assert!(!verify_signature(SECRET, BODY, &"é".repeat(32)));
+        // truncated
+        assert!(!verify_signature(SECRET, BODY, &SIGNATURE[..62]));
+        assert!(!verify_signature(SECRET, BODY, ""));
+    }
+
+    #[test]
+    fn decode_hex_decodes_bytes() {
+        assert_eq!(decode_hex("00ff7F"), Some(vec![0x00, 0xff, 0x7f]));
+        assert_eq!(decode_hex(""), Some(vec![]));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("0g"), None);
+        assert_eq!(decode_hex("+1"), None);
+    }
+
+    #[test]
+    fn unknown_event_keeps_name_and_data() {
+        let data = serde_json::json!({ "type": "affiliates", "id": "1" });
+        let event = WebhookEvent::from_data("affiliate_activated", data.clone()).unwrap();
+
+        match event {
+            WebhookEvent::Unknown {
+                event_name,
+                data: event_data,
+            } => {
+                assert_eq!(event_name, "affiliate_activated");
+                assert_eq!(event_data, data);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn known_event_with_invalid_data_fails() {
+        let data = serde_json::json!({ "type": "orders", "id": "1" });
+
+        assert!(WebhookEvent::from_data("order_created", data).is_err());
+    }
+
+    #[test]
+    fn payload_defaults_missing_custom_data() {
+        let payload: WebhookPayload = serde_json::from_str(
+            r#"{"meta": {"event_name": "affiliate_activated"}, "data": {"id": "1"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(payload.meta.event_name, "affiliate_activated");
+        assert!(!payload.meta.test_mode);
+        assert_eq!(payload.meta.custom_data, serde_json::Value::Null);
+        assert!(matches!(payload.event, WebhookEvent::Unknown { .. }));
+    }
+}
@@ -1,6 +1,16 @@
+pub mod auth;
+pub mod billing;
+pub mod inbound_mail;
+pub mod license;
 pub mod oauth;
 pub mod order;
+pub mod personal_access_token;
+pub mod plan;
+pub mod referral;
+pub mod session;
+pub mod subscription;
 pub mod todo;
+pub mod usage;
 pub mod user;
 pub mod webhook;
 
@@ -44,10 +54,3 @@ impl Error for AppError {
         None
     }
 }
-
-#[derive(Clone, Serialize)]
-pub struct UserData {
-    pub id: i64,
-    pub email: String,
-    pub name: String,
-}
@@ -0,0 +1,92 @@
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    extract::{self, ConnectInfo, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+
+use super::{AppError, AppState};
+use crate::services::{
+    auth::{revoke_refresh_token, rotate_refresh_token},
+    session::SessionClient,
+};
+
+/** Length of the `user_agent` column */
+const MAX_USER_AGENT_LENGTH: usize = 255;
+
+/**
+ * Number of reverse proxies in front of the app, `TRUSTED_PROXY_COUNT`, none by default.
+ * Each proxy appends the address it received the request from to `X-Forwarded-For`.
+ */
+fn get_trusted_proxy_count() -> usize {
+    env::var("TRUSTED_PROXY_COUNT")
+        .ok()
+        .and_then(|count| count.parse::<usize>().ok())
+        .unwrap_or(0)
+}
+
+/**
+ * Device of the request, shown in the session list
+ * Only the `X-Forwarded-For` addresses appended by the trusted proxies are used, the ones before
+ * them are sent by the client and can be anything.
+ */
+pub fn get_session_client(headers: &HeaderMap, addr: SocketAddr) -> SessionClient {
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+        .map(|user_agent| user_agent.chars().take(MAX_USER_AGENT_LENGTH).collect());
+    let ip_address = match get_trusted_proxy_count() {
+        0 => None,
+        count => headers
+            .get("x-forwarded-for")
+            .and_then(|forwarded_for| forwarded_for.to_str().ok())
+            .and_then(|forwarded_for| forwarded_for.rsplit(',').nth(count - 1))
+            .and_then(|ip_address| ip_address.trim().parse::<IpAddr>().ok()),
+    }
+    .unwrap_or_else(|| addr.ip());
+
+    SessionClient {
+        user_agent,
+        ip_address: Some(ip_address.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RefreshTokenParams {
+    refresh_token: String,
+}
+
+/** Exchange a refresh token for a new access token and a new refresh token */
+pub async fn refresh_token(
+    state: State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    extract::Json(params): extract::Json<RefreshTokenParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let client = get_session_client(&headers, addr);
+    let tokens = rotate_refresh_token(&state.conn, &params.refresh_token, client)
+        .await
+        .map_err(|err| match err.code {
+            "invalid_refresh_token" => (StatusCode::UNAUTHORIZED, Json(err)),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, Json(err)),
+        })?;
+
+    Ok(Json(tokens))
+}
+
+pub async fn logout(
+    state: State<AppState>,
+    extract::Json(params): extract::Json<RefreshTokenParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    revoke_refresh_token(&state.conn, &params.refresh_token)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(()))
+}
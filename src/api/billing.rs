@@ -0,0 +1,67 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use entity::users;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{AppError, AppState};
+use crate::services::billing::{self, get_user_invoices};
+
+#[derive(Deserialize)]
+pub struct GetInvoicesParams {
+    /** Starts from 1 */
+    page: Option<u64>,
+    page_size: Option<u64>,
+}
+
+/** Invoices and receipts of the current user */
+pub async fn get_invoices(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+    Query(params): Query<GetInvoicesParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let page = params.page.unwrap_or(1).max(1);
+    let page_size = params.page_size.unwrap_or(20).clamp(1, 100);
+
+    let (invoices, total) = get_user_invoices(&state.conn, &user, page - 1, page_size)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(json!({
+        "page": page,
+        "page_size": page_size,
+        "total": total,
+        "invoices": invoices
+            .iter()
+            .map(|invoice| {
+                json!({
+                    "id": invoice.id,
+                    "kind": invoice.kind,
+                    "billing_reason": invoice.billing_reason,
+                    "status": invoice.status,
+                    "currency": invoice.currency,
+                    "total": invoice.total,
+                    "total_formatted": invoice.total_formatted,
+                    "refunded": invoice.refunded != 0,
+                    "invoice_url": invoice.invoice_url,
+                    "issued_at": invoice.issued_at,
+                })
+            })
+            .collect::<Vec<serde_json::Value>>(),
+    })))
+}
+
+/** Import invoices and orders made before invoices were recorded */
+pub async fn backfill_invoices(
+    state: State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let report = billing::backfill_invoices(&state)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(report))
+}
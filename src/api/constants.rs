@@ -20,6 +20,26 @@ impl TryFrom<i32> for TodoStatus {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ExtractHistoryStatus {
+    Reserved = 0,
+    Committed = 1,
+    Released = 2,
+}
+
+impl TryFrom<i32> for ExtractHistoryStatus {
+    type Error = &'static str;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ExtractHistoryStatus::Reserved),
+            1 => Ok(ExtractHistoryStatus::Committed),
+            2 => Ok(ExtractHistoryStatus::Released),
+            _ => Err("Invalid extract history status"),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum SubscriptionType {
     Free = 1,
@@ -48,6 +68,26 @@ impl From<SubscriptionType> for String {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+pub enum PlanKind {
+    Subscription = 1,
+    /** One-time purchase of extra extraction credits */
+    CreditPack = 2,
+}
+
+impl TryFrom<i32> for PlanKind {
+    type Error = &'static str;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(PlanKind::Subscription),
+            2 => Ok(PlanKind::CreditPack),
+            _ => Err("Invalid plan kind"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OrderStatus {
     Created = 0,
     Finished = 1,
@@ -63,8 +103,88 @@ impl TryFrom<i32> for OrderStatus {
             0 => Ok(OrderStatus::Created),
             1 => Ok(OrderStatus::Finished),
             2 => Ok(OrderStatus::Cancelled),
-            4 => Ok(OrderStatus::Timeout),
+            3 => Ok(OrderStatus::Timeout),
             _ => Err("Invalid order status"),
         }
     }
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum WebhookEventStatus {
+    /** Waiting for (another) attempt at `next_attempt_at` */
+    Pending = 0,
+    /** Claimed by a worker until `next_attempt_at` */
+    Processing = 1,
+    Succeeded = 2,
+    /** Retries exhausted, needs a replay */
+    Failed = 3,
+}
+
+impl TryFrom<i32> for WebhookEventStatus {
+    type Error = &'static str;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(WebhookEventStatus::Pending),
+            1 => Ok(WebhookEventStatus::Processing),
+            2 => Ok(WebhookEventStatus::Succeeded),
+            3 => Ok(WebhookEventStatus::Failed),
+            _ => Err("Invalid webhook event status"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ReferralStatus {
+    /** The referee signed up, waiting for the first payment */
+    SignedUp = 0,
+    /** The referrer got the bonus credits */
+    Rewarded = 1,
+}
+
+impl TryFrom<i32> for ReferralStatus {
+    type Error = &'static str;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ReferralStatus::SignedUp),
+            1 => Ok(ReferralStatus::Rewarded),
+            _ => Err("Invalid referral status"),
+        }
+    }
+}
+
+/** Permissions of a personal access token, stored comma separated */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TokenScope {
+    #[serde(rename = "events:read")]
+    EventsRead,
+    #[serde(rename = "events:write")]
+    EventsWrite,
+    /** Extract events from text or web pages, uses the extraction quota */
+    #[serde(rename = "extract")]
+    Extract,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::EventsRead => "events:read",
+            TokenScope::EventsWrite => "events:write",
+            TokenScope::Extract => "extract",
+        }
+    }
+}
+
+impl TryFrom<&str> for TokenScope {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "events:read" => Ok(TokenScope::EventsRead),
+            "events:write" => Ok(TokenScope::EventsWrite),
+            "extract" => Ok(TokenScope::Extract),
+            _ => Err("Invalid token scope"),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::env;
+
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chrono::{DateTime, Local, Utc};
+use entity::{todos, users};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{constants::TodoStatus, todo::extract_event_from_text, AppError, AppState};
+use crate::services::mail::{
+    self, get_mail_dedupe_key, is_inbound_mail_recorded, record_inbound_mail,
+};
+
+/** Characters of mail text sent to LLM at most */
+const MAX_MAIL_TEXT_LENGTH: usize = 3000;
+/** Calendar events of a mail that become todos at most, the rest are ignored */
+const MAX_CALENDAR_EVENTS: usize = 20;
+
+fn get_inbound_mail_domain() -> String {
+    env::var("INBOUND_MAIL_DOMAIN")
+        .expect("INBOUND_MAIL_DOMAIN is not set in .env file")
+        .to_lowercase()
+}
+
+/** Get the inbound address of the user. The address is created on first request. */
+pub async fn get_inbound_address(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let token = match user.inbound_mail_token.clone() {
+        Some(token) => token,
+        None => {
+            let token = uuid::Uuid::new_v4().simple().to_string();
+            let mut modified_user: users::ActiveModel = user.into();
+            modified_user.inbound_mail_token = Set(Some(token.clone()));
+            modified_user.save(&state.conn).await.map_err(|err| {
+                sentry::capture_error(&err);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(AppError {
+                        code: "database_error",
+                        message: "Please try again later.",
+                    }),
+                )
+            })?;
+            token
+        }
+    };
+
+    Ok(Json(json!({
+        "address": format!("{}@{}", token, get_inbound_mail_domain()),
+    })))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InboundMailQuery {
+    /** Envelope recipient provided by the inbound-parse service */
+    recipient: Option<String>,
+}
+
+/**
+ * Inbound-parse webhook. The body is the raw RFC 5322 message.
+ * Calendar attachments with a definite start time become todos directly, otherwise the mail
+ * content goes through the extraction flow and is counted against the quota.
+ * The todos of a mail are created together, retries of an already processed mail create nothing.
+ */
+pub async fn handle_inbound_mail(
+    state: State<AppState>,
+    Query(query): Query<InboundMailQuery>,
+    body: Bytes,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let inbound_mail =
+        mail::parse_inbound_mail(&body).map_err(|err| (StatusCode::BAD_REQUEST, Json(err)))?;
+
+    let domain_suffix = format!("@{}", get_inbound_mail_domain());
+    let token = query
+        .recipient
+        .map(|recipient| recipient.to_lowercase())
+        .into_iter()
+        .chain(inbound_mail.recipients.clone())
+        .find_map(|address| {
+            address
+                .strip_suffix(&domain_suffix)
+                .map(|token| token.to_owned())
+        })
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(AppError {
+                code: "recipient_not_found",
+                message: "",
+            }),
+        ))?;
+
+    let user = users::Entity::find()
+        .filter(users::Column::InboundMailToken.eq(token))
+        .one(&state.conn)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(AppError {
+                    code: "database_error",
+                    message: "",
+                }),
+            )
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(AppError {
+                code: "recipient_not_found",
+                message: "",
+            }),
+        ))?;
+
+    let dedupe_key = get_mail_dedupe_key(inbound_mail.message_id.as_deref(), &body);
+    if is_inbound_mail_recorded(&state.conn, user.id, &dedupe_key)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?
+    {
+        return Ok(Json(json!({ "events": [] })));
+    }
+
+    let description: String = inbound_mail.subject.chars().take(300).collect();
+    let mut new_events = Vec::new();
+    let mut undetermined_events = Vec::new();
+
+    for event in inbound_mail
+        .calendar_events
+        .iter()
+        .take(MAX_CALENDAR_EVENTS)
+    {
+        if let Some(start_time) = event.start_time {
+            let event_name: String = event.summary.chars().take(200).collect();
+            new_events.push((event_name, start_time));
+        } else {
+            undetermined_events.push(event.raw.clone());
+        }
+    }
+
+    if new_events.is_empty() {
+        let text = if undetermined_events.is_empty() {
+            format!("{}\n{}", inbound_mail.subject, inbound_mail.text)
+        } else {
+            undetermined_events.join("\n")
+        };
+        let text: String = text.chars().take(MAX_MAIL_TEXT_LENGTH).collect();
+        let current_time = inbound_mail
+            .date
+            .unwrap_or_else(Utc::now)
+            .with_timezone(&Local);
+
+        let result = extract_event_from_text(&state, &user, current_time, &text).await?;
+        new_events.push((result.event_name, result.scheduled_time));
+    }
+
+    let txn = state.conn.begin().await.map_err(database_error)?;
+    if !record_inbound_mail(&txn, user.id, &dedupe_key)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?
+    {
+        return Ok(Json(json!({ "events": [] })));
+    }
+    let mut created_events = Vec::new();
+    for (event_name, scheduled_time) in new_events {
+        created_events
+            .push(create_todo(&txn, &user, event_name, scheduled_time, &description).await?);
+    }
+    txn.commit().await.map_err(database_error)?;
+
+    Ok(Json(json!({
+        "events": created_events
+            .iter()
+            .map(|event| json!({
+                "id": event.id,
+                "event_name": event.event_name,
+                "scheduled_time": event.scheduled_time,
+            }))
+            .collect::<Vec<serde_json::Value>>(),
+    })))
+}
+
+fn database_error(err: sea_orm::DbErr) -> (StatusCode, Json<AppError>) {
+    sentry::capture_error(&err);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(AppError {
+            code: "database_error",
+            message: "Failed to create event. Please try again later.",
+        }),
+    )
+}
+
+async fn create_todo<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    event_name: String,
+    scheduled_time: DateTime<Utc>,
+    description: &str,
+) -> Result<todos::Model, (StatusCode, Json<AppError>)> {
+    todos::ActiveModel {
+        user_id: Set(user.id),
+        event_name: Set(event_name),
+        description: Set(Some(description.to_owned())),
+        scheduled_time: Set(Some(scheduled_time)),
+        remind_time: Set(Some(scheduled_time)),
+        status: Set(TodoStatus::Created as i32),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .map_err(database_error)
+}
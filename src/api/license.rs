@@ -0,0 +1,270 @@
+use std::env;
+
+use axum::{
+    extract::{self, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use entity::{user_licenses, users};
+use lemon_squeezy::LemonSqueezyError;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{constants::PlanKind, AppError, AppState};
+use crate::services::{
+    license::{
+        bind_license_key, delete_license, get_user_license, get_user_licenses,
+        save_license_activation, save_license_validation,
+    },
+    plan::get_plan_by_variant_id,
+};
+
+fn get_lemon_squeezy_client() -> lemon_squeezy::LemonSqueezy {
+    lemon_squeezy::LemonSqueezy::new(
+        env::var("LEMON_SQUEEZY_API_KEY").expect("LEMON_SQUEEZY_API_KEY is not set in .env file"),
+    )
+}
+
+fn lemon_squeezy_error(err: LemonSqueezyError) -> (StatusCode, Json<AppError>) {
+    match err {
+        LemonSqueezyError::RateLimited { .. } => (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(AppError {
+                code: "rate_limited",
+                message: "Too many requests. Please try again later.",
+            }),
+        ),
+        _ => {
+            sentry::capture_error(&err);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(AppError {
+                    code: "failed_to_check_license",
+                    message: "Failed to check the license key. Please try again later.",
+                }),
+            )
+        }
+    }
+}
+
+fn invalid_license_key() -> (StatusCode, Json<AppError>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(AppError {
+            code: "invalid_license_key",
+            message: "The license key is invalid.",
+        }),
+    )
+}
+
+/** The license key itself is only shown to Lemon Squeezy, never returned */
+#[derive(Serialize)]
+pub struct LicenseSummary {
+    instance_id: String,
+    instance_name: String,
+    product_id: i32,
+    variant_id: i32,
+    status: String,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    last_validated_at: chrono::DateTime<chrono::Utc>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<user_licenses::Model> for LicenseSummary {
+    fn from(license: user_licenses::Model) -> Self {
+        LicenseSummary {
+            instance_id: license.instance_id,
+            instance_name: license.instance_name,
+            product_id: license.product_id,
+            variant_id: license.variant_id,
+            status: license.status,
+            expires_at: license.expires_at,
+            last_validated_at: license.last_validated_at,
+            created_at: license.created_at,
+        }
+    }
+}
+
+async fn find_user_license(
+    state: &State<AppState>,
+    user: &users::Model,
+    instance_id: &str,
+) -> Result<user_licenses::Model, (StatusCode, Json<AppError>)> {
+    get_user_license(&state.conn, user, instance_id)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(AppError {
+                code: "license_not_found",
+                message: "No license found.",
+            }),
+        ))
+}
+
+#[derive(Deserialize)]
+pub struct ActivateLicenseParams {
+    license_key: String,
+    /** Shown in the customer portal, e.g. the device name */
+    instance_name: String,
+}
+
+/**
+ * 激活授权码并绑定到当前用户
+ * 先校验授权码属于本店铺且对应订阅套餐存在，绑定到当前用户后再占用一次激活
+ */
+pub async fn activate_license(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+    extract::Json(params): extract::Json<ActivateLicenseParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let client = get_lemon_squeezy_client();
+    let store_id = env::var("LEMON_SQUEEZY_STORE_ID")
+        .expect("LEMON_SQUEEZY_STORE_ID is not set in .env file")
+        .parse::<i32>()
+        .expect("LEMON_SQUEEZY_STORE_ID is not a valid store id");
+
+    let validation = client
+        .validate_license(&params.license_key, None)
+        .await
+        .map_err(lemon_squeezy_error)?;
+    let (Some(license_key), Some(meta)) = (validation.license_key, validation.meta) else {
+        return Err(invalid_license_key());
+    };
+    if meta.store_id != store_id {
+        return Err(invalid_license_key());
+    }
+
+    let plan = get_plan_by_variant_id(&state.conn, meta.variant_id)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+    // credit packs are one-off purchases, a license key must grant a subscription plan
+    if plan.map_or(true, |plan| plan.kind == PlanKind::CreditPack as i32) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(AppError {
+                code: "license_not_supported",
+                message: "The license key does not grant any plan.",
+            }),
+        ));
+    }
+
+    let bound = bind_license_key(&state.conn, license_key.id, user.id)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+    if !bound {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(AppError {
+                code: "license_bound_to_another_user",
+                message: "The license key is used by another account.",
+            }),
+        ));
+    }
+
+    let activation = client
+        .activate_license(&params.license_key, &params.instance_name)
+        .await
+        .map_err(lemon_squeezy_error)?;
+    let (true, Some(license_key), Some(instance), Some(meta)) = (
+        activation.activated,
+        activation.license_key,
+        activation.instance,
+        activation.meta,
+    ) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(AppError {
+                code: "license_activation_failed",
+                message: "The license key could not be activated.",
+            }),
+        ));
+    };
+
+    let license = save_license_activation(&state.conn, user.id, &license_key, &instance, &meta)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(LicenseSummary::from(license)))
+}
+
+#[derive(Deserialize)]
+pub struct LicenseInstanceParams {
+    instance_id: String,
+}
+
+/** Called by the desktop app on start, keeps the entitlement in sync with the license key */
+pub async fn validate_license(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+    extract::Json(params): extract::Json<LicenseInstanceParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let license = find_user_license(&state, &user, &params.instance_id).await?;
+
+    let validation = get_lemon_squeezy_client()
+        .validate_license(&license.license_key, Some(&license.instance_id))
+        .await
+        .map_err(lemon_squeezy_error)?;
+
+    let license = save_license_validation(
+        &state.conn,
+        &license,
+        validation.license_key.as_ref(),
+        validation.valid,
+    )
+    .await
+    .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(json!({
+        "valid": validation.valid,
+        "license": LicenseSummary::from(license),
+    })))
+}
+
+/** Free an activation, e.g. before moving the app to another device */
+pub async fn deactivate_license(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+    extract::Json(params): extract::Json<LicenseInstanceParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let license = find_user_license(&state, &user, &params.instance_id).await?;
+
+    let deactivation = get_lemon_squeezy_client()
+        .deactivate_license(&license.license_key, &license.instance_id)
+        .await
+        .map_err(lemon_squeezy_error)?;
+
+    // a license key unknown to Lemon Squeezy, e.g. a deleted one, has nothing left to deactivate
+    if !deactivation.deactivated && deactivation.license_key.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(AppError {
+                code: "license_deactivation_failed",
+                message: "The license could not be deactivated.",
+            }),
+        ));
+    }
+
+    delete_license(&state.conn, license)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(()))
+}
+
+pub async fn get_licenses(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let licenses = get_user_licenses(&state.conn, &user)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(
+        licenses
+            .into_iter()
+            .map(LicenseSummary::from)
+            .collect::<Vec<_>>(),
+    ))
+}
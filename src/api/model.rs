@@ -2,8 +2,11 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenClaims {
+    /** Id of the user, the email may change and should not be exposed */
     pub sub: String,
     pub name: String,
     pub iat: usize,
     pub exp: usize,
+    /** Session of the token, checked on every request so revoked sessions log out at once */
+    pub sid: String,
 }
@@ -1,25 +1,27 @@
-use std::{collections::HashMap, env};
+use std::{collections::HashMap, env, net::SocketAddr};
 
 use anyhow::anyhow;
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    extract::{ConnectInfo, Query, State},
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Redirect},
     Json,
 };
-use chrono;
 use entity::{oauth2_state_storage, users};
-use jsonwebtoken::{encode, EncodingKey, Header};
 use oauth2::{
     basic::BasicClient, reqwest::http_client, AuthUrl, AuthorizationCode, ClientId, ClientSecret,
     CsrfToken, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, RevocationUrl, Scope,
     TokenResponse, TokenUrl,
 };
 use reqwest;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, EntityTrait, QueryFilter,
+    QueryOrder,
+};
 use url;
 
-use super::{model::TokenClaims, AppError, AppState};
+use super::{auth::get_session_client, AppError, AppState};
+use crate::services::{auth::issue_tokens, referral::record_referral};
 
 fn get_oauth_client() -> Result<BasicClient, anyhow::Error> {
     let google_client_id = ClientId::new(
@@ -67,6 +69,8 @@ pub async fn login(
     let return_url = params
         .remove("return_url")
         .unwrap_or_else(|| "/".to_string());
+    // `?ref=CODE` of an invitation link, applied if the login creates a new user
+    let referral_code = params.remove("ref");
     let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
     let (authorize_url, csrf_state) = oauth_client
         .authorize_url(CsrfToken::new_random)
@@ -87,6 +91,7 @@ pub async fn login(
         csrf_state: Set(csrf_state.secret().to_owned()),
         pkce_code_verifier: Set(pkce_code_verifier.secret().to_owned()),
         return_url: Set(return_url),
+        referral_code: Set(referral_code),
         ..Default::default()
     }
     .save(&state.conn)
@@ -107,6 +112,8 @@ pub async fn login(
 pub async fn oauth_callback(
     Query(mut params): Query<HashMap<String, String>>,
     State(app_state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
     let state = CsrfToken::new(params.remove("state").ok_or((
         StatusCode::BAD_REQUEST,
@@ -158,6 +165,7 @@ pub async fn oauth_callback(
 
     let pkce_code_verifier = PkceCodeVerifier::new(result.pkce_code_verifier);
     let return_url = result.return_url;
+    let referral_code = result.referral_code;
     let oauth_client = get_oauth_client().map_err(|err| {
         sentry::integrations::anyhow::capture_anyhow(&err);
         (
@@ -250,6 +258,19 @@ pub async fn oauth_callback(
         )
     })?;
 
+    // the `sub` of the Google account, unlike the email it never changes
+    let google_id = body["id"]
+        .take()
+        .as_str()
+        .ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(AppError {
+                code: "missing_google_id",
+                message: "",
+            }),
+        ))?
+        .to_owned();
+
     let email = body["email"]
         .take()
         .as_str()
@@ -270,11 +291,7 @@ pub async fn oauth_callback(
         }),
     ))?;
 
-    let first_name = body["family_name"]
-        .take()
-        .as_str()
-        .unwrap_or("")
-        .to_owned();
+    let first_name = body["family_name"].take().as_str().unwrap_or("").to_owned();
 
     let last_name = body["given_name"].take().as_str().unwrap_or("").to_owned();
     let avatar = body["picture"].take().as_str().unwrap_or("").to_owned();
@@ -292,7 +309,17 @@ pub async fn oauth_callback(
     // Create user if not exists
 
     let existed_user = users::Entity::find()
-        .filter(users::Column::Email.eq(&email))
+        .filter(
+            Condition::any()
+                .add(users::Column::GoogleId.eq(&google_id))
+                // accounts created before `google_id` was stored are matched once by email
+                .add(
+                    Condition::all()
+                        .add(users::Column::GoogleId.is_null())
+                        .add(users::Column::Email.eq(&email)),
+                ),
+        )
+        .order_by_desc(users::Column::GoogleId)
         .one(&app_state.conn)
         .await
         .map_err(|err| {
@@ -306,19 +333,38 @@ pub async fn oauth_callback(
             )
         })?;
 
-    if existed_user.is_none() {
+    let user = if let Some(existed_user) = existed_user {
+        // Refresh tokens
+        let mut modified_user: users::ActiveModel = existed_user.into();
+        modified_user.google_id = Set(Some(google_id.clone()));
+        // follow email changes of the Google account
+        modified_user.email = Set(email.clone());
+        modified_user.google_access_token = Set(access_token.to_owned());
+        modified_user.google_refresh_token = Set(refresh_token.to_owned());
+        modified_user.update(&app_state.conn).await.map_err(|err| {
+            sentry::capture_error(&err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(AppError {
+                    code: "refresh_token_error",
+                    message: "",
+                }),
+            )
+        })?
+    } else {
         // Create user
-        users::ActiveModel {
+        let new_user = users::ActiveModel {
             first_name: Set(first_name.clone()),
             last_name: Set(last_name.clone()),
             avatar: Set(avatar.clone()),
             email: Set(email.clone()),
+            google_id: Set(Some(google_id.clone())),
             google_access_token: Set(access_token.to_owned()),
             google_refresh_token: Set(refresh_token.to_owned()),
             has_google_calendar_access: Set(has_calendar_access),
             ..Default::default()
         }
-        .save(&app_state.conn)
+        .insert(&app_state.conn)
         .await
         .map_err(|err| {
             sentry::capture_error(&err);
@@ -330,44 +376,23 @@ pub async fn oauth_callback(
                 }),
             )
         })?;
-    } else {
-        // Refresh tokens
-        let mut modified_user: users::ActiveModel = existed_user.unwrap().into();
-        modified_user.google_access_token = Set(access_token.to_owned());
-        modified_user.google_refresh_token = Set(refresh_token.to_owned());
-        modified_user.save(&app_state.conn).await.map_err(|err| {
-            sentry::capture_error(&err);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AppError {
-                    code: "refresh_token_error",
-                    message: "",
-                }),
-            )
-        })?;
-    }
 
-    // issue JWT token
-    let now = chrono::Utc::now();
-    let iat = now.timestamp() as usize;
-    let exp = (now + chrono::Duration::minutes(60 * 24 * 30 * 12)).timestamp() as usize;
-    let claims: TokenClaims = TokenClaims {
-        sub: email,
-        name: first_name,
-        exp,
-        iat,
+        // an invalid invitation must not block the sign-up
+        if let Some(referral_code) = referral_code {
+            let _ = record_referral(&app_state.conn, &new_user, &referral_code)
+                .await
+                .map_err(|err| {
+                    sentry::capture_error(&err);
+                });
+        }
+
+        new_user
     };
 
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(
-            env::var("JWT_SECRET")
-                .expect("JWT_SECRET is not set in .env file")
-                .as_ref(),
-        ),
-    )
-    .unwrap();
+    // start a session with a short-lived access token and a refresh token
+    let tokens = issue_tokens(&app_state.conn, &user, get_session_client(&headers, addr))
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
 
     let redirect_url = url::Url::parse(&return_url).map_err(|_| {
         (
@@ -379,7 +404,11 @@ pub async fn oauth_callback(
         )
     })?;
 
-    let final_url = format!("{}/{}", redirect_url, token);
+    // the fragment is not sent to any server
+    let final_url = format!(
+        "{}/{}#refresh_token={}",
+        redirect_url, tokens.access_token, tokens.refresh_token
+    );
 
-    return Ok(Redirect::to(final_url.as_str()));
+    Ok(Redirect::to(final_url.as_str()))
 }
@@ -7,7 +7,7 @@ use axum::{
     Extension, Json,
 };
 use entity::{orders, users};
-use lemon_squeezy::CreateCheckoutParams;
+use lemon_squeezy::{CheckoutOptions, CheckoutProductOptions, CreateCheckoutParams};
 use sea_orm::{
     ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, EntityTrait, QueryFilter,
 };
@@ -15,6 +15,11 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use super::{constants::OrderStatus, AppError, AppState};
+use crate::services::{
+    order::get_order_expiry_window,
+    plan::{get_public_paid_plans, get_purchasable_plan},
+    referral::get_referee_discount_code,
+};
 
 #[derive(Serialize, Deserialize)]
 pub struct CreateOrderParams {
@@ -25,6 +30,13 @@ pub struct CreateOrderParams {
      * LemonSqueezy -- Redirect --> Backend -- Redirect --> Chrome Extension
      */
     redirect_url: Option<String>,
+    /** Plan to purchase. Defaults to the first public paid plan. */
+    plan_id: Option<i32>,
+    /** Promotion code applied to the checkout */
+    discount_code: Option<String>,
+    /** The extension opens the checkout in an overlay instead of a new tab */
+    embed: Option<bool>,
+    dark: Option<bool>,
 }
 
 pub async fn crate_order(
@@ -32,7 +44,7 @@ pub async fn crate_order(
     Extension(user): Extension<users::Model>,
     extract::Json(params): extract::Json<CreateOrderParams>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
-    let email = user.email;
+    let email = user.email.clone();
     let redirect_url = params.redirect_url.ok_or((
         StatusCode::BAD_REQUEST,
         Json(AppError {
@@ -40,6 +52,40 @@ pub async fn crate_order(
             message: "",
         }),
     ))?;
+
+    let plan = match params.plan_id {
+        Some(plan_id) => get_purchasable_plan(&state.conn, plan_id)
+            .await
+            .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?,
+        None => get_public_paid_plans(&state.conn)
+            .await
+            .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?
+            .into_iter()
+            .next(),
+    }
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(AppError {
+            code: "plan_not_found",
+            message: "Plan not found.",
+        }),
+    ))?;
+
+    let (Some(product_id), Some(variant_id)) = (plan.product_id, plan.variant_id) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(AppError {
+                code: "plan_not_purchasable",
+                message: "The plan cannot be purchased.",
+            }),
+        ));
+    };
+
+    let store_id = env::var("LEMON_SQUEEZY_STORE_ID")
+        .expect("LEMON_SQUEEZY_STORE_ID is not set in .env file")
+        .parse::<i32>()
+        .expect("LEMON_SQUEEZY_STORE_ID is not a valid store id");
+
     let client = lemon_squeezy::LemonSqueezy::new(
         env::var("LEMON_SQUEEZY_API_KEY").expect("LEMON_SQUEEZY_API_KEY is not set in .env file"),
     );
@@ -47,22 +93,54 @@ pub async fn crate_order(
     let internal_order_id = uuid::Uuid::new_v4().to_string();
 
     let app_endpoint = env::var("APP_ENDPOINT").expect("APP_ENDPOINT is not set in .env file");
+    let callback_url = format!(
+        "{}/order/checkout_callback?internal_order_id={}",
+        app_endpoint, internal_order_id
+    );
+    let test_mode = env::var("LEMON_SQUEEZY_TEST_MODE")
+        .ok()
+        .and_then(|test_mode| test_mode.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    // invited users get their referral discount unless they entered another code
+    let discount_code = match params.discount_code {
+        Some(discount_code) => Some(discount_code),
+        None => get_referee_discount_code(&state.conn, &user)
+            .await
+            .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?,
+    };
+
     let create_order_result = client
         .create_checkout(CreateCheckoutParams {
             email: Some(email),
-            store_id: 43821,
-            variant_id: 138344,
-            redirect_url: format!(
-                "{}/order/checkout_callback?internal_order_id={}",
-                app_endpoint, internal_order_id
-            ),
+            store_id,
+            variant_id,
+            discount_code,
             custom_data: json!({
                 "internal_order_id": internal_order_id,
             }),
+            // the checkout link expires together with the unpaid order
+            expires_at: Some((chrono::Utc::now() + get_order_expiry_window()).to_rfc3339()),
+            product_options: CheckoutProductOptions {
+                name: Some(plan.name.clone()),
+                description: plan.description.clone(),
+                redirect_url: Some(callback_url.clone()),
+                receipt_link_url: Some(callback_url),
+                enabled_variants: vec![variant_id],
+                ..Default::default()
+            },
+            checkout_options: CheckoutOptions {
+                embed: params.embed,
+                dark: params.dark,
+                discount: Some(true),
+                ..Default::default()
+            },
+            test_mode,
+            ..Default::default()
         })
         .await
         .map_err(|err| {
-            sentry::integrations::anyhow::capture_anyhow(&err);
+            sentry::capture_error(&err);
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(AppError {
@@ -74,8 +152,8 @@ pub async fn crate_order(
 
     let new_order = orders::ActiveModel {
         user_id: Set(user.id),
-        product_id: Set(120215),
-        variant_id: Set(138344),
+        product_id: Set(product_id),
+        variant_id: Set(variant_id),
         status: Set(OrderStatus::Created as i32),
         internal_order_id: Set(internal_order_id.clone()),
         redirect_url: Set(format!("{}/{}", redirect_url, internal_order_id.clone())),
@@ -95,7 +173,9 @@ pub async fn crate_order(
 
     Ok(Json(json!({
         "checkout_url": create_order_result.attributes.url,
-        "order_status": result.status
+        "checkout_expires_at": create_order_result.attributes.expires_at,
+        "internal_order_id": result.internal_order_id,
+        "order_status": OrderStatus::Created as i32
     })))
 }
 
@@ -146,6 +226,21 @@ pub struct CheckOrderStatusParams {
     internal_order_id: Option<String>,
 }
 
+/** Same fields as the order row the extension already reads, plus `status_name` */
+#[derive(Serialize)]
+pub struct CheckOrderStatusResult {
+    id: i32,
+    product_id: i32,
+    variant_id: i32,
+    internal_order_id: String,
+    user_id: i32,
+    redirect_url: String,
+    status: i32,
+    /** One of `created`, `finished`, `cancelled` or `timeout` */
+    status_name: OrderStatus,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
 pub async fn check_order_status(
     state: State<AppState>,
     Extension(user): Extension<users::Model>,
@@ -165,7 +260,6 @@ pub async fn check_order_status(
                 .add(orders::Column::InternalOrderId.eq(internal_order_id))
                 .add(orders::Column::UserId.eq(user.id)),
         )
-        .into_json()
         .one(&state.conn)
         .await
         .map_err(|err| {
@@ -186,5 +280,26 @@ pub async fn check_order_status(
             }),
         ))?;
 
-    Ok(Json(order))
+    let status_name = OrderStatus::try_from(order.status).map_err(|err| {
+        sentry::capture_message(err, sentry::Level::Error);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(AppError {
+                code: "invalid_order_status",
+                message: "",
+            }),
+        )
+    })?;
+
+    Ok(Json(CheckOrderStatusResult {
+        id: order.id,
+        product_id: order.product_id,
+        variant_id: order.variant_id,
+        internal_order_id: order.internal_order_id,
+        user_id: order.user_id,
+        redirect_url: order.redirect_url,
+        status: order.status,
+        status_name,
+        created_at: order.created_at,
+    }))
 }
@@ -0,0 +1,151 @@
+use axum::{
+    extract::{self, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chrono::{Duration, Utc};
+use entity::{personal_access_tokens, users};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{constants::TokenScope, AppError, AppState};
+use crate::services::personal_access_token::{
+    get_token_scopes, get_user_personal_access_tokens, issue_personal_access_token,
+    revoke_user_personal_access_token,
+};
+
+/** Length of the `name` column */
+const MAX_TOKEN_NAME_LENGTH: usize = 100;
+/** About ten years, `expires_at` is a `DATETIME` as a `TIMESTAMP` ends in 2038 */
+const MAX_TOKEN_EXPIRY_DAYS: i64 = 3650;
+
+/** The token itself is only returned once, when it is created */
+#[derive(Serialize)]
+pub struct PersonalAccessTokenSummary {
+    id: i32,
+    name: String,
+    token_prefix: String,
+    scopes: Vec<TokenScope>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<personal_access_tokens::Model> for PersonalAccessTokenSummary {
+    fn from(token: personal_access_tokens::Model) -> Self {
+        PersonalAccessTokenSummary {
+            scopes: get_token_scopes(&token),
+            id: token.id,
+            name: token.name,
+            token_prefix: token.token_prefix,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            created_at: token.created_at,
+        }
+    }
+}
+
+pub async fn get_personal_access_tokens(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let tokens = get_user_personal_access_tokens(&state.conn, &user)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(
+        tokens
+            .into_iter()
+            .map(PersonalAccessTokenSummary::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct CreatePersonalAccessTokenParams {
+    name: String,
+    scopes: Vec<TokenScope>,
+    /** Never expires if not set */
+    expires_in_days: Option<i64>,
+}
+
+/** Create a token for scripts and integrations that cannot log in with Google */
+pub async fn create_personal_access_token(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+    extract::Json(params): extract::Json<CreatePersonalAccessTokenParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let name = params.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_TOKEN_NAME_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(AppError {
+                code: "invalid_token_name",
+                message: "The name must have 1 to 100 characters.",
+            }),
+        ));
+    }
+    if params.scopes.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(AppError {
+                code: "missing_scopes",
+                message: "Please choose at least one scope.",
+            }),
+        ));
+    }
+    let expires_at = match params.expires_in_days {
+        // cannot overflow within the range
+        Some(days) if (1..=MAX_TOKEN_EXPIRY_DAYS).contains(&days) => {
+            Some(Utc::now() + Duration::days(days))
+        }
+        Some(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(AppError {
+                    code: "invalid_expiry",
+                    message: "The token must expire in 1 to 3650 days.",
+                }),
+            ))
+        }
+        None => None,
+    };
+
+    let (token, personal_access_token) =
+        issue_personal_access_token(&state.conn, &user, name, &params.scopes, expires_at)
+            .await
+            .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(json!({
+        "token": token,
+        "personal_access_token": PersonalAccessTokenSummary::from(personal_access_token),
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct RevokePersonalAccessTokenParams {
+    id: i32,
+}
+
+pub async fn revoke_personal_access_token(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+    extract::Json(params): extract::Json<RevokePersonalAccessTokenParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let revoked = revoke_user_personal_access_token(&state.conn, &user, params.id)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    if !revoked {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(AppError {
+                code: "token_not_found",
+                message: "No access token found.",
+            }),
+        ));
+    }
+
+    Ok(Json(()))
+}
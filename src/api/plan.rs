@@ -0,0 +1,34 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+
+use super::{AppError, AppState};
+use crate::services::plan::{get_plan_features, get_plan_models, get_public_plans};
+
+pub async fn get_plans(
+    state: State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let plans = get_public_plans(&state.conn)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(
+        plans
+            .iter()
+            .map(|plan| {
+                json!({
+                    "id": plan.id,
+                    "name": plan.name,
+                    "description": plan.description,
+                    "type": plan.r#type,
+                    "kind": plan.kind,
+                    "price": plan.price,
+                    "quota": plan.quota,
+                    "credit_validity_days": plan.credit_validity_days,
+                    "models": get_plan_models(plan),
+                    "features": get_plan_features(plan),
+                    "purchasable": plan.variant_id.is_some(),
+                })
+            })
+            .collect::<Vec<serde_json::Value>>(),
+    ))
+}
@@ -0,0 +1,28 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension, Json};
+use entity::users;
+use serde_json::json;
+
+use super::{AppError, AppState};
+use crate::services::referral::{
+    get_or_create_referral_code, get_referral_bonus_credits, get_referral_stats,
+};
+
+/** Referral code of the user and how many invited users signed up and paid */
+pub async fn get_referral_info(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let referral_code = get_or_create_referral_code(&state.conn, &user)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+    let stats = get_referral_stats(&state.conn, &user)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(json!({
+        "referral_code": referral_code,
+        "signed_up_count": stats.signed_up_count,
+        "rewarded_count": stats.rewarded_count,
+        "bonus_credits_per_referral": get_referral_bonus_credits(),
+    })))
+}
@@ -0,0 +1,87 @@
+use axum::{
+    extract::{self, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use entity::{sessions, users};
+use serde::{Deserialize, Serialize};
+
+use super::{AppError, AppState};
+use crate::services::session::{get_user_sessions, revoke_other_sessions, revoke_user_session};
+
+#[derive(Serialize)]
+pub struct SessionSummary {
+    id: String,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+    /** The session of this request */
+    current: bool,
+    last_used_at: chrono::DateTime<chrono::Utc>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub async fn get_sessions(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+    Extension(current_session): Extension<sessions::Model>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let sessions = get_user_sessions(&state.conn, &user)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| SessionSummary {
+                current: session.id == current_session.id,
+                id: session.id,
+                user_agent: session.user_agent,
+                ip_address: session.ip_address,
+                last_used_at: session.last_used_at,
+                created_at: session.created_at,
+            })
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct RevokeSessionParams {
+    session_id: String,
+}
+
+/** Sign out a device, revoking the current session logs out this device */
+pub async fn revoke_session(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+    extract::Json(params): extract::Json<RevokeSessionParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let revoked = revoke_user_session(&state.conn, &user, &params.session_id)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    if !revoked {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(AppError {
+                code: "session_not_found",
+                message: "No session found.",
+            }),
+        ));
+    }
+
+    Ok(Json(()))
+}
+
+/** Sign out all other devices */
+pub async fn revoke_all_sessions(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+    Extension(current_session): Extension<sessions::Model>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    revoke_other_sessions(&state.conn, &user, &current_session.id)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(()))
+}
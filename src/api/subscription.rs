@@ -0,0 +1,233 @@
+use std::env;
+
+use axum::{
+    extract::{self, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use entity::{user_subscriptions, users};
+use lemon_squeezy::{LemonSqueezyError, SubscriptionObject, UpdateSubscriptionParams};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{constants::PlanKind, AppError, AppState};
+use crate::services::{
+    plan::get_purchasable_plan,
+    reconciliation,
+    subscription::{get_latest_subscription, save_lemon_squeezy_subscription},
+};
+
+fn get_lemon_squeezy_client() -> lemon_squeezy::LemonSqueezy {
+    lemon_squeezy::LemonSqueezy::new(
+        env::var("LEMON_SQUEEZY_API_KEY").expect("LEMON_SQUEEZY_API_KEY is not set in .env file"),
+    )
+}
+
+fn lemon_squeezy_error(err: LemonSqueezyError) -> (StatusCode, Json<AppError>) {
+    match err {
+        LemonSqueezyError::NotFound(_) => (
+            StatusCode::NOT_FOUND,
+            Json(AppError {
+                code: "subscription_not_found",
+                message: "No subscription found.",
+            }),
+        ),
+        LemonSqueezyError::Validation(_) => {
+            sentry::capture_error(&err);
+            (
+                StatusCode::BAD_REQUEST,
+                Json(AppError {
+                    code: "invalid_subscription_update",
+                    message: "The subscription cannot be updated this way.",
+                }),
+            )
+        }
+        LemonSqueezyError::RateLimited { .. } => (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(AppError {
+                code: "rate_limited",
+                message: "Too many requests. Please try again later.",
+            }),
+        ),
+        _ => {
+            sentry::capture_error(&err);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(AppError {
+                    code: "failed_to_update_subscription",
+                    message: "Failed to update subscription. Please try again later.",
+                }),
+            )
+        }
+    }
+}
+
+/** Subscription of the user and its id at Lemon Squeezy */
+async fn get_user_subscription(
+    state: &State<AppState>,
+    user: &users::Model,
+) -> Result<(user_subscriptions::Model, i32), (StatusCode, Json<AppError>)> {
+    let subscription = get_latest_subscription(&state.conn, user)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(AppError {
+                code: "subscription_not_found",
+                message: "No subscription found.",
+            }),
+        ))?;
+
+    let external_subscription_id = subscription
+        .external_subscription_id
+        .parse::<i32>()
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(AppError {
+                    code: "invalid_subscription_id",
+                    message: "",
+                }),
+            )
+        })?;
+
+    Ok((subscription, external_subscription_id))
+}
+
+/** Save the remote subscription returned by an action and respond with its state */
+async fn save_and_respond(
+    state: &State<AppState>,
+    user: &users::Model,
+    subscription: SubscriptionObject,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<AppError>)> {
+    save_lemon_squeezy_subscription(&state.conn, user.id, &subscription)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(json!({
+        "status": subscription.attributes.status,
+        "variant_id": subscription.attributes.variant_id,
+        "renews_at": subscription.attributes.renews_at,
+        "ends_at": subscription.attributes.ends_at,
+    })))
+}
+
+/** Customer portal and payment method URLs, valid for 24 hours */
+pub async fn get_subscription_portal(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let (_, external_subscription_id) = get_user_subscription(&state, &user).await?;
+
+    let urls = get_lemon_squeezy_client()
+        .get_subscription_urls(external_subscription_id)
+        .await
+        .map_err(lemon_squeezy_error)?;
+
+    Ok(Json(json!({
+        "customer_portal_url": urls.customer_portal,
+        "update_payment_method_url": urls.update_payment_method,
+    })))
+}
+
+/** 取消订阅，当前周期结束前仍可使用 */
+pub async fn cancel_subscription(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let (_, external_subscription_id) = get_user_subscription(&state, &user).await?;
+
+    let subscription = get_lemon_squeezy_client()
+        .cancel_subscription(external_subscription_id)
+        .await
+        .map_err(lemon_squeezy_error)?;
+
+    save_and_respond(&state, &user, subscription).await
+}
+
+/** 恢复已取消但未过期的订阅 */
+pub async fn resume_subscription(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let (_, external_subscription_id) = get_user_subscription(&state, &user).await?;
+
+    let subscription = get_lemon_squeezy_client()
+        .resume_subscription(external_subscription_id)
+        .await
+        .map_err(lemon_squeezy_error)?;
+
+    save_and_respond(&state, &user, subscription).await
+}
+
+#[derive(Deserialize)]
+pub struct ChangeSubscriptionPlanPayload {
+    plan_id: i32,
+}
+
+/** 切换套餐，按比例计费 */
+pub async fn change_subscription_plan(
+    state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+    extract::Json(payload): extract::Json<ChangeSubscriptionPlanPayload>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let (user_subscription, external_subscription_id) =
+        get_user_subscription(&state, &user).await?;
+
+    // credit packs and hidden plans cannot be subscribed to
+    let plan = get_purchasable_plan(&state.conn, payload.plan_id)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?
+        .filter(|plan| plan.kind == PlanKind::Subscription as i32)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(AppError {
+                code: "plan_not_found",
+                message: "Plan not found.",
+            }),
+        ))?;
+
+    let variant_id = plan.variant_id.ok_or((
+        StatusCode::BAD_REQUEST,
+        Json(AppError {
+            code: "plan_not_purchasable",
+            message: "The plan cannot be purchased.",
+        }),
+    ))?;
+
+    if variant_id == user_subscription.variant_id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(AppError {
+                code: "already_on_plan",
+                message: "You are already on this plan.",
+            }),
+        ));
+    }
+
+    let subscription = get_lemon_squeezy_client()
+        .update_subscription(
+            external_subscription_id,
+            UpdateSubscriptionParams {
+                variant_id: Some(variant_id),
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(lemon_squeezy_error)?;
+
+    save_and_respond(&state, &user, subscription).await
+}
+
+/** Reconcile subscriptions with Lemon Squeezy now instead of waiting for the job */
+pub async fn reconcile_subscriptions(
+    state: State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let report = reconciliation::reconcile_subscriptions(&state)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(report))
+}
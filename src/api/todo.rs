@@ -14,15 +14,8 @@ use sea_orm::{
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use super::{
-    constants::{SubscriptionType, TodoStatus},
-    AppError, AppState,
-};
-use crate::services::{
-    extract_history::{self},
-    openai,
-    subscription::get_user_quota_and_subscription,
-};
+use super::{constants::TodoStatus, AppError, AppState};
+use crate::services::{openai, plan::get_plan_models, quota, usage::ExtractionUsage, web_page};
 #[derive(Deserialize)]
 pub struct GetUpcomingEventPayload {
     current_time: Option<String>,
@@ -88,7 +81,7 @@ pub async fn get_upcoming_events(
                 }),
             )
         })?;
-    return Ok(Json(result));
+    Ok(Json(result))
 }
 
 #[derive(Deserialize)]
@@ -182,9 +175,9 @@ pub struct PrepareCreateEventPayload {
 }
 
 #[derive(Serialize, Deserialize)]
-struct PrepareCreateEventResult {
-    event_name: String,
-    scheduled_time: DateTime<Utc>,
+pub struct PrepareCreateEventResult {
+    pub event_name: String,
+    pub scheduled_time: DateTime<Utc>,
 }
 
 pub async fn prepare_create_event(
@@ -230,42 +223,81 @@ pub async fn prepare_create_event(
         ));
     }
 
-    // check user subscriptions
-    let quota_and_subscription_info = get_user_quota_and_subscription(&app_state, &user)
-        .await
-        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+    let result =
+        extract_event_from_text(&app_state, &user, current_time, &event_description).await?;
 
-    if quota_and_subscription_info.quota_info.quota
-        <= quota_and_subscription_info.quota_info.used_count
-    {
-        return Err((
+    Ok(Json(result))
+}
+
+/**
+ * Extract event name and time from the given text with LLM.
+ * The call is counted against the quota of the user.
+ */
+pub async fn extract_event_from_text(
+    app_state: &State<AppState>,
+    user: &users::Model,
+    current_time: DateTime<Local>,
+    text: &str,
+) -> Result<PrepareCreateEventResult, (StatusCode, Json<AppError>)> {
+    // check user subscriptions and hold one quota unit
+    let reservation = quota::reserve_quota(app_state, user)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?
+        .ok_or((
             StatusCode::FORBIDDEN,
             Json(AppError {
                 code: "exceed_quota",
                 message: "Quota exceed. Please try again later or upgrade your plan.",
             }),
-        ));
-    }
-
-    // get completion from openai
-    let mut selected_model = "gpt-3.5-turbo";
+        ))?;
 
-    // apply GPT-4 for paid user
-    if let Some(subscription) = quota_and_subscription_info.subscription {
-        if subscription.r#type == SubscriptionType::Pro as i32 {
-            selected_model = "gpt-4o";
-        }
-    }
+    // get completion from openai with the default model of the plan
+    let selected_model = get_plan_models(&reservation.plan)
+        .first()
+        .cloned()
+        .unwrap_or_else(|| "gpt-3.5-turbo".to_owned());
 
-    let prompt = format!("Please extract the event details from the given text. The extracted information should include 'event_time' (the event's start time) and 'name' (the event's name).  The 'event_time' should be presented in ISO format, such as \"2023-01-01T20:00:00Z\". If the text is in a foreign language, deduce the timezone from the language used. For instance, if the text is in Japanese, the timezone should correspond to Japan's. Your timezone should be correct. If the text indicates a time beyond 24:00, interpret it according to the 30-hour system used in Japan, where, for example, 26:00 refers to 2:00 on the following day. If both start and end times are given, use the start time. Your response should be in JSON format, like this: {{\"name\": \"Event Name\", \"event_time\": \"2023-01-01T20:00:00Z\"}}. Ensure your response is accurate and straightforward, without including any explanations or error messages. The current time is: {}. Think it carefully and I will tip you $200 if your answer is correct. Please process the following text: {}", format!("{:?}", current_time), event_description);
+    let prompt = format!("Please extract the event details from the given text. The extracted information should include 'event_time' (the event's start time) and 'name' (the event's name).  The 'event_time' should be presented in ISO format, such as \"2023-01-01T20:00:00Z\". If the text is in a foreign language, deduce the timezone from the language used. For instance, if the text is in Japanese, the timezone should correspond to Japan's. Your timezone should be correct. If the text indicates a time beyond 24:00, interpret it according to the 30-hour system used in Japan, where, for example, 26:00 refers to 2:00 on the following day. If both start and end times are given, use the start time. Your response should be in JSON format, like this: {{\"name\": \"Event Name\", \"event_time\": \"2023-01-01T20:00:00Z\"}}. Ensure your response is accurate and straightforward, without including any explanations or error messages. The current time is: {:?}. Think it carefully and I will tip you $200 if your answer is correct. Please process the following text: {}", current_time, text);
 
-    let openai_result = openai::get_completion(selected_model, &prompt)
-        .await
-        .map_err(|err| {
+    let completion = match openai::get_completion(&selected_model, &prompt).await {
+        Ok(completion) => completion,
+        Err(err) => {
             sentry::capture_error(&err);
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
-        })?;
+            // failed extractions do not consume quota
+            let _ = quota::release_quota(app_state, &reservation, None)
+                .await
+                .map_err(|err| {
+                    sentry::capture_error(&err);
+                });
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(err)));
+        }
+    };
+    let usage = ExtractionUsage::from(&completion);
+
+    match parse_extracted_event(&completion.content) {
+        Ok(result) => {
+            quota::commit_quota(app_state, &reservation, &prompt, &usage)
+                .await
+                .map_err(|err| {
+                    sentry::capture_error(&err);
+                    (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
+                })?;
+            Ok(result)
+        }
+        Err(err) => {
+            let _ = quota::release_quota(app_state, &reservation, Some(&usage))
+                .await
+                .map_err(|err| {
+                    sentry::capture_error(&err);
+                });
+            Err(err)
+        }
+    }
+}
 
+fn parse_extracted_event(
+    openai_result: &str,
+) -> Result<PrepareCreateEventResult, (StatusCode, Json<AppError>)> {
     let result = openai_result.replace("\n", "");
 
     let filtered_regex = Regex::new(r"(?<main>\{.+})").unwrap();
@@ -329,17 +361,91 @@ pub async fn prepare_create_event(
             )
         })?;
 
-    extract_history::record_extract_history(&app_state, &user, &prompt)
-        .await
-        .map_err(|err| {
-            sentry::capture_error(&err);
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
-        })?;
-
-    Ok(Json(PrepareCreateEventResult {
+    Ok(PrepareCreateEventResult {
         event_name,
         scheduled_time: event_time,
-    }))
+    })
+}
+
+#[derive(Deserialize)]
+pub struct PrepareCreateEventFromUrlPayload {
+    current_time: Option<String>,
+    url: Option<String>,
+}
+
+/** Characters of page text sent to LLM at most */
+const MAX_PAGE_TEXT_LENGTH: usize = 3000;
+
+pub async fn prepare_create_event_from_url(
+    app_state: State<AppState>,
+    Extension(user): Extension<users::Model>,
+    extract::Json(params): extract::Json<PrepareCreateEventFromUrlPayload>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let current_time = params
+        .current_time
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            Json(AppError {
+                code: "missing_current_time",
+                message: "",
+            }),
+        ))?
+        .parse::<DateTime<Local>>()
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(AppError {
+                    code: "invalid_time",
+                    message: "",
+                }),
+            )
+        })?;
+    let url = params.url.ok_or((
+        StatusCode::BAD_REQUEST,
+        Json(AppError {
+            code: "missing_url",
+            message: "A URL of the event page is required.",
+        }),
+    ))?;
+
+    let html = web_page::fetch_page(&url)
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(err)))?;
+
+    // structured data is more reliable than LLM, use it directly when the time zone is known
+    let text = if let Some(event) = web_page::extract_json_ld_event(&html) {
+        if let Ok(scheduled_time) = DateTime::parse_from_rfc3339(&event.start_date) {
+            return Ok(Json(PrepareCreateEventResult {
+                event_name: event.name,
+                scheduled_time: scheduled_time.with_timezone(&Utc),
+            }));
+        }
+        format!(
+            "{}\n{}\n{}",
+            event.name,
+            event.start_date,
+            event.location.unwrap_or_default()
+        )
+    } else {
+        web_page::html_to_text(&html)
+            .chars()
+            .take(MAX_PAGE_TEXT_LENGTH)
+            .collect()
+    };
+
+    if text.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(AppError {
+                code: "empty_page",
+                message: "No readable content found on the page.",
+            }),
+        ));
+    }
+
+    let result = extract_event_from_text(&app_state, &user, current_time, &text).await?;
+
+    Ok(Json(result))
 }
 
 #[derive(Serialize, Deserialize)]
@@ -0,0 +1,133 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use entity::users;
+use sea_orm::EntityTrait;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{AppError, AppState};
+use crate::services::{subscription::get_user_quota_and_subscription, usage};
+
+#[derive(Deserialize)]
+pub struct GetUsageReportParams {
+    user_id: Option<i32>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+}
+
+fn parse_report_time(
+    time: Option<String>,
+    default_time: DateTime<Utc>,
+) -> Result<DateTime<Utc>, (StatusCode, Json<AppError>)> {
+    match time {
+        Some(time) => time.parse::<DateTime<Utc>>().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(AppError {
+                    code: "invalid_time",
+                    message: "",
+                }),
+            )
+        }),
+        None => Ok(default_time),
+    }
+}
+
+/** Usage of a single user. Defaults to the current quota period of the user. */
+pub async fn get_user_usage_report(
+    state: State<AppState>,
+    Query(params): Query<GetUsageReportParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let user_id = params.user_id.ok_or((
+        StatusCode::BAD_REQUEST,
+        Json(AppError {
+            code: "missing_user_id",
+            message: "",
+        }),
+    ))?;
+
+    let user = users::Entity::find_by_id(user_id)
+        .one(&state.conn)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(AppError {
+                    code: "database_error",
+                    message: "",
+                }),
+            )
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(AppError {
+                code: "user_not_found",
+                message: "",
+            }),
+        ))?;
+
+    let quota_and_subscription_info = get_user_quota_and_subscription(&state.conn, &user)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    let start_time = parse_report_time(
+        params.start_time,
+        quota_and_subscription_info.quota_info.period_start,
+    )?;
+    let end_time = parse_report_time(
+        params.end_time,
+        quota_and_subscription_info.quota_info.period_end,
+    )?;
+
+    let by_model = usage::get_usage_by_model(&state.conn, Some(user.id), start_time, end_time)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(json!({
+        "user_id": user.id,
+        "start_time": start_time,
+        "end_time": end_time,
+        "quota": quota_and_subscription_info.quota_info.quota,
+        "used_count": quota_and_subscription_info.quota_info.used_count,
+        "request_count": by_model.iter().map(|usage| usage.request_count).sum::<i64>(),
+        "prompt_tokens": by_model.iter().map(|usage| usage.prompt_tokens).sum::<i64>(),
+        "completion_tokens": by_model.iter().map(|usage| usage.completion_tokens).sum::<i64>(),
+        "cost_micro_usd": by_model.iter().map(|usage| usage.cost_micro_usd).sum::<i64>(),
+        "by_model": by_model,
+    })))
+}
+
+/** Usage of all users grouped by plan and model. Defaults to the last 30 days. */
+pub async fn get_global_usage_report(
+    state: State<AppState>,
+    Query(params): Query<GetUsageReportParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let now = Utc::now();
+    let start_time = parse_report_time(params.start_time, now - Duration::days(30))?;
+    let end_time = parse_report_time(params.end_time, now)?;
+
+    let by_plan = usage::get_usage_by_plan(&state.conn, start_time, end_time)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+    let by_model = usage::get_usage_by_model(&state.conn, None, start_time, end_time)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+    let top_users = usage::get_usage_by_user(&state.conn, start_time, end_time, 50)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    Ok(Json(json!({
+        "start_time": start_time,
+        "end_time": end_time,
+        "cost_micro_usd": by_model.iter().map(|usage| usage.cost_micro_usd).sum::<i64>(),
+        "by_plan": by_plan,
+        "by_model": by_model,
+        "top_users": top_users,
+    })))
+}
@@ -4,50 +4,36 @@ use serde_json::json;
 
 use crate::services::subscription::get_user_quota_and_subscription;
 
-use super::{constants::SubscriptionType, AppError, AppState};
+use super::{AppError, AppState};
 
 pub async fn get_user_profile(
     state: State<AppState>,
     Extension(user): Extension<users::Model>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
-    let user_quota_and_subscription = get_user_quota_and_subscription(&state, &user)
+    let user_quota_and_subscription = get_user_quota_and_subscription(&state.conn, &user)
         .await
         .map_err(|err| {
             sentry::capture_error(&err);
             (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
         })?;
 
-    let subscription_info;
-
-    if user_quota_and_subscription.subscription.is_some() {
-        let subscription = user_quota_and_subscription.subscription.unwrap();
-        let subscription_type: SubscriptionType = subscription.r#type.try_into().map_err(|_| {
-            sentry::capture_message(
-                "Failed to convert subscription type to text.",
-                sentry::Level::Error,
-            );
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AppError {
-                    code: "invalid_subscription_type",
-                    message: "Invalid subscription type.",
-                }),
-            )
-        })?;
-        let subscription_name: String = subscription_type.into();
-
-        subscription_info = json!({
-            "subscription_name": subscription_name,
+    let plan = &user_quota_and_subscription.plan;
+    let subscription_info = if let Some(subscription) = user_quota_and_subscription.subscription {
+        json!({
+            "plan_id": plan.id,
+            "subscription_name": plan.name,
             "subscription_type": subscription.r#type,
+            "status": subscription.status,
             "start_time": subscription.start_time,
-            "renews_at": subscription.renews_at
-        });
+            "renews_at": subscription.renews_at,
+            "ends_at": subscription.ends_at
+        })
     } else {
-        let subscription_name: String = SubscriptionType::Free.into();
-        subscription_info = json!({
-            "subscription_name": subscription_name,
-        });
-    }
+        json!({
+            "plan_id": plan.id,
+            "subscription_name": plan.name,
+        })
+    };
 
     Ok(Json(json!({
         "first_name": user.first_name,
@@ -56,7 +42,17 @@ pub async fn get_user_profile(
         "quota_info": json!({
             "quota": user_quota_and_subscription.quota_info.quota,
             "used_count": user_quota_and_subscription.quota_info.used_count,
+            "period_start": user_quota_and_subscription.quota_info.period_start,
+            "period_end": user_quota_and_subscription.quota_info.period_end,
+            "resets_at": user_quota_and_subscription.quota_info.period_end,
+            "credits": user_quota_and_subscription.quota_info.credits,
         }),
         "subscription": subscription_info,
+        "license": user_quota_and_subscription.license.map(|license| json!({
+            "instance_name": license.instance_name,
+            "status": license.status,
+            "expires_at": license.expires_at,
+        })),
+        "entitlement": user_quota_and_subscription.entitlement,
     })))
 }
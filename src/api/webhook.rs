@@ -1,123 +1,101 @@
 use axum::{
-    extract::{self, State},
+    extract::{self, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
 };
-use entity::{orders, users};
-use lemon_squeezy::SubscriptionInvoiceObject;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
+use entity::webhook_events;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use crate::services::subscription::sync_subscription_status_with_lemon_squeezy;
+use lemon_squeezy::WebhookEnvelope;
 
-use super::{constants::OrderStatus, AppError, AppState};
-
-#[derive(Serialize, Deserialize)]
-pub struct LemonSqueezyWebhookPayload<T> {
-    meta: LemonSqueezyWebhookPayloadMeta<T>,
-    data: serde_json::Value,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct LemonSqueezyWebhookPayloadMeta<T> {
-    event_name: String,
-    custom_data: T,
-}
+use crate::services::webhook::{
+    self, process_webhook_event, record_webhook_event, LemonSqueezyWebhookCustomData,
+    LEMON_SQUEEZY_SOURCE,
+};
 
-#[derive(Serialize, Deserialize)]
-pub struct LemonSqueezyWebhookCustomData {
-    pub internal_order_id: Option<String>,
-}
+use super::{AppError, AppState};
 
+/**
+ * 接收 webhook
+ * 事件先持久化再异步处理，重复投递的事件直接返回
+ */
 pub async fn handle_lemon_squeezy_webhook(
     state: State<AppState>,
-    extract::Json(params): extract::Json<LemonSqueezyWebhookPayload<LemonSqueezyWebhookCustomData>>,
+    body: String,
 ) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
-    let event_name = params.meta.event_name.clone();
-    let internal_order_id = params.meta.custom_data.internal_order_id.clone();
-
-    if internal_order_id.is_none() {
-        sentry::capture_message(
-            "Missing internal_order_id for webhook event.",
-            sentry::Level::Error,
-        );
-        return Ok(Json({}));
-    }
-
-    let internal_order_id = internal_order_id.unwrap();
-
-    let order = orders::Entity::find()
-        .filter(orders::Column::InternalOrderId.eq(internal_order_id))
-        .one(&state.conn)
-        .await
+    // data is decoded when the event is processed, so a payload we can't decode yet is still kept
+    let payload: WebhookEnvelope<LemonSqueezyWebhookCustomData> = serde_json::from_str(&body)
         .map_err(|err| {
             sentry::capture_error(&err);
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_REQUEST,
                 Json(AppError {
-                    code: "database_error",
+                    code: "failed_to_parse_webhook_payload",
                     message: "",
                 }),
             )
         })?;
 
-    if order.is_none() {
-        sentry::capture_message("Corresponding order not found.", sentry::Level::Error);
-        return Ok(Json({}));
+    let event = record_webhook_event(
+        &state.conn,
+        LEMON_SQUEEZY_SOURCE,
+        &payload.meta.event_name,
+        &body,
+    )
+    .await
+    .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    if let Some(event) = event {
+        let app_state = state.0.clone();
+        tokio::spawn(async move {
+            let _ = process_webhook_event(&app_state, event.id)
+                .await
+                .map_err(|err| {
+                    sentry::capture_error(&err);
+                });
+        });
     }
 
-    let order = order.unwrap();
+    Ok(Json(()))
+}
 
-    match event_name.as_str() {
-        "subscription_payment_success" => {
-            let result = handle_subscription_payment_success(state, params, order).await;
-            return result;
-        }
-        _ => Ok(Json({})),
-    }
+#[derive(Deserialize)]
+pub struct GetWebhookEventsParams {
+    status: Option<i32>,
+    limit: Option<u64>,
 }
 
-/**
- * 订阅成功
- * 1. 更新订单状态
- * 2. 同步订阅状态
- */
-pub async fn handle_subscription_payment_success(
+#[derive(Serialize)]
+pub struct WebhookEventSummary {
+    id: i32,
+    source: String,
+    event_name: String,
+    status: i32,
+    attempts: i32,
+    error: Option<String>,
+    next_attempt_at: chrono::DateTime<chrono::Utc>,
+    processed_at: Option<chrono::DateTime<chrono::Utc>>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/** Latest webhook events, optionally filtered by `WebhookEventStatus` */
+pub async fn get_webhook_events(
     state: State<AppState>,
-    payload: LemonSqueezyWebhookPayload<LemonSqueezyWebhookCustomData>,
-    order: orders::Model,
-) -> Result<Json<()>, (StatusCode, Json<AppError>)> {
-    let payload: SubscriptionInvoiceObject =
-        serde_json::from_value(payload.data).map_err(|err| {
-            sentry::capture_error(&err);
-            (
-                StatusCode::BAD_REQUEST,
-                Json(AppError {
-                    code: "failed_to_parse_webhook_payload",
-                    message: "",
-                }),
-            )
-        })?;
+    Query(params): Query<GetWebhookEventsParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let mut query = webhook_events::Entity::find();
 
-    if order.status == OrderStatus::Created as i32 {
-        let mut order: orders::ActiveModel = order.clone().into();
-        order.status = Set(OrderStatus::Finished as i32);
-        let _ = order.save(&state.conn).await.map_err(|err| {
-            sentry::capture_error(&err);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AppError {
-                    code: "database_error",
-                    message: "",
-                }),
-            )
-        })?;
+    if let Some(status) = params.status {
+        query = query.filter(webhook_events::Column::Status.eq(status));
     }
 
-    let user = users::Entity::find()
-        .filter(users::Column::Id.eq(order.user_id))
-        .one(&state.conn)
+    let events = query
+        .order_by_desc(webhook_events::Column::Id)
+        .limit(params.limit.unwrap_or(50).min(500))
+        .all(&state.conn)
         .await
         .map_err(|err| {
             sentry::capture_error(&err);
@@ -128,24 +106,49 @@ pub async fn handle_subscription_payment_success(
                     message: "",
                 }),
             )
-        })?
-        .ok_or((
-            StatusCode::NOT_FOUND,
-            Json(AppError {
-                code: "user_not_found",
-                message: "",
-            }),
-        ))?;
-
-    let _ = sync_subscription_status_with_lemon_squeezy(
-        &state,
-        user,
-        payload.attributes.subscription_id,
-    )
-    .await
-    .map_err(|err| {
-        sentry::capture_error(&err);
-    });
+        })?;
 
-    Ok(Json(()))
+    Ok(Json(
+        events
+            .into_iter()
+            .map(|event| WebhookEventSummary {
+                id: event.id,
+                source: event.source,
+                event_name: event.event_name,
+                status: event.status,
+                attempts: event.attempts,
+                error: event.error,
+                next_attempt_at: event.next_attempt_at,
+                processed_at: event.processed_at,
+                created_at: event.created_at,
+            })
+            .collect::<Vec<WebhookEventSummary>>(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct ReplayWebhookEventsPayload {
+    /** Replays all failed events when absent */
+    id: Option<i32>,
+}
+
+pub async fn replay_webhook_events(
+    state: State<AppState>,
+    extract::Json(payload): extract::Json<ReplayWebhookEventsPayload>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let event_ids = webhook::replay_webhook_events(&state.conn, payload.id)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?;
+
+    let mut failed_event_ids = vec![];
+    for event_id in &event_ids {
+        if process_webhook_event(&state, *event_id).await.is_err() {
+            failed_event_ids.push(*event_id);
+        }
+    }
+
+    Ok(Json(json!({
+        "replayed": event_ids,
+        "failed": failed_event_ids,
+    })))
 }
@@ -0,0 +1,18 @@
+use std::time::Duration;
+
+use crate::{api::AppState, services::order::expire_unpaid_orders};
+
+const SWEEP_INTERVAL_SECONDS: u64 = 5 * 60;
+
+/** Moves abandoned orders out of `Created` */
+pub async fn run_order_expiry_job(app_state: AppState) {
+    let mut interval = tokio::time::interval(Duration::from_secs(SWEEP_INTERVAL_SECONDS));
+
+    loop {
+        interval.tick().await;
+
+        let _ = expire_unpaid_orders(&app_state.conn).await.map_err(|err| {
+            sentry::capture_error(&err);
+        });
+    }
+}
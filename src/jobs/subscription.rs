@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use tokio::time::{interval_at, Instant};
+
+use crate::{api::AppState, services::reconciliation::reconcile_subscriptions};
+
+const RECONCILIATION_INTERVAL_SECONDS: u64 = 6 * 60 * 60;
+
+/** Fixes subscriptions left stale by missed webhooks */
+pub async fn run_reconciliation_job(app_state: AppState) {
+    let period = Duration::from_secs(RECONCILIATION_INTERVAL_SECONDS);
+    let mut interval = interval_at(Instant::now() + period, period);
+
+    loop {
+        interval.tick().await;
+
+        // each run in its own task, so that a panic does not stop later runs
+        let app_state = app_state.clone();
+        let run = tokio::spawn(async move { reconcile_subscriptions(&app_state).await });
+        match run.await {
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => {
+                sentry::capture_error(&err);
+            }
+            Err(err) => {
+                sentry::capture_error(&err);
+            }
+        }
+    }
+}
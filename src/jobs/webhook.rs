@@ -0,0 +1,18 @@
+use std::time::Duration;
+
+use crate::{api::AppState, services::webhook::process_due_webhook_events};
+
+const POLL_INTERVAL_SECONDS: u64 = 30;
+
+/** Retries webhook events that failed or were lost while being processed */
+pub async fn run_webhook_worker(app_state: AppState) {
+    let mut interval = tokio::time::interval(Duration::from_secs(POLL_INTERVAL_SECONDS));
+
+    loop {
+        interval.tick().await;
+
+        let _ = process_due_webhook_events(&app_state).await.map_err(|err| {
+            sentry::capture_error(&err);
+        });
+    }
+}
@@ -1,30 +1,50 @@
 mod api;
+mod jobs;
 mod middlewares;
 mod services;
 
-use std::env;
+use std::{env, net::SocketAddr};
 
 use api::{
+    auth::{logout, refresh_token},
+    billing::{backfill_invoices, get_invoices},
+    inbound_mail::{get_inbound_address, handle_inbound_mail},
+    license::{activate_license, deactivate_license, get_licenses, validate_license},
     oauth::{login, oauth_callback},
     order::{check_order_status, checkout_callback, crate_order},
+    personal_access_token::{
+        create_personal_access_token, get_personal_access_tokens, revoke_personal_access_token,
+    },
+    plan::get_plans,
+    referral::get_referral_info,
+    session::{get_sessions, revoke_all_sessions, revoke_session},
+    subscription::{
+        cancel_subscription, change_subscription_plan, get_subscription_portal,
+        reconcile_subscriptions, resume_subscription,
+    },
     todo::{
-        create_event, delete_event, get_upcoming_events, prepare_create_event, update_event,
-        update_event_status,
+        create_event, delete_event, get_upcoming_events, prepare_create_event,
+        prepare_create_event_from_url, update_event, update_event_status,
     },
+    usage::{get_global_usage_report, get_user_usage_report},
     user::get_user_profile,
-    webhook::handle_lemon_squeezy_webhook,
+    webhook::{get_webhook_events, handle_lemon_squeezy_webhook, replay_webhook_events},
     AppState,
 };
 use axum::{
+    extract::DefaultBodyLimit,
     http::{header, Method},
     middleware,
     routing::{get, post},
     Router,
 };
-use middlewares::{jwt_auth, lemon_squeezy_webhook_auth};
+use middlewares::{admin_auth, inbound_mail_auth, jwt_auth, lemon_squeezy_webhook_auth};
 use sea_orm::Database;
 use tower_http::cors::{Any, CorsLayer};
 
+/** Raw mails may carry attachments, allow a larger body than the default 2MB */
+const MAX_INBOUND_MAIL_SIZE: usize = 10 * 1024 * 1024;
+
 fn main() {
     dotenvy::dotenv().ok();
     let _guard = sentry::init((
@@ -44,6 +64,9 @@ fn main() {
                 .with_max_level(tracing::Level::DEBUG)
                 .init();
 
+            // fail fast on an invalid `OPENAI_PRICE_TABLE`
+            services::usage::get_price_table();
+
             let db_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
 
             let conn = Database::connect(db_url)
@@ -52,22 +75,64 @@ fn main() {
 
             let state = AppState { conn };
 
+            if env::args().nth(1).as_deref() == Some("replay-webhooks") {
+                replay_webhooks(&state, env::args().nth(2)).await;
+                return;
+            }
+
+            tokio::spawn(jobs::webhook::run_webhook_worker(state.clone()));
+            tokio::spawn(jobs::order::run_order_expiry_job(state.clone()));
+            tokio::spawn(jobs::subscription::run_reconciliation_job(state.clone()));
+
+            let admin_routes = Router::new()
+                .route("/usage/report", get(get_global_usage_report))
+                .route("/usage/user_report", get(get_user_usage_report))
+                .route("/subscriptions/reconcile", post(reconcile_subscriptions))
+                .route("/billing/backfill_invoices", post(backfill_invoices))
+                .route("/webhook_events", get(get_webhook_events))
+                .route("/webhook_events/replay", post(replay_webhook_events))
+                .layer(middleware::from_fn(admin_auth::auth));
+
             // build our application with a single route
             let app = Router::new()
                 .route("/user/profile", get(get_user_profile))
+                .route("/user/inbound_address", get(get_inbound_address))
+                .route("/user/referral", get(get_referral_info))
+                .route("/session/list", get(get_sessions))
+                .route("/session/revoke", post(revoke_session))
+                .route("/session/revoke_all", post(revoke_all_sessions))
+                .route("/token/list", get(get_personal_access_tokens))
+                .route("/token/create", post(create_personal_access_token))
+                .route("/token/revoke", post(revoke_personal_access_token))
                 .route("/event/upcoming", get(get_upcoming_events))
                 .route("/event/update_status", post(update_event_status))
                 .route("/event/prepare_create", post(prepare_create_event))
+                .route(
+                    "/event/prepare_create_from_url",
+                    post(prepare_create_event_from_url),
+                )
                 .route("/event/create", post(create_event))
                 .route("/event/update", post(update_event))
                 .route("/event/delete", post(delete_event))
                 .route("/order/checkout", post(crate_order))
                 .route("/order/check_order_status", get(check_order_status))
+                .route("/subscription/portal", get(get_subscription_portal))
+                .route("/billing/invoices", get(get_invoices))
+                .route("/subscription/cancel", post(cancel_subscription))
+                .route("/subscription/resume", post(resume_subscription))
+                .route("/subscription/change_plan", post(change_subscription_plan))
+                .route("/license/list", get(get_licenses))
+                .route("/license/activate", post(activate_license))
+                .route("/license/validate", post(validate_license))
+                .route("/license/deactivate", post(deactivate_license))
                 .layer(middleware::from_fn_with_state(
                     state.clone(),
                     jwt_auth::auth,
                 ))
                 .route("/", get(|| async { "Hello, World!" }))
+                .route("/plans", get(get_plans))
+                .route("/auth/refresh", post(refresh_token))
+                .route("/auth/logout", post(logout))
                 .layer(
                     CorsLayer::new()
                         .allow_methods([Method::GET, Method::POST])
@@ -75,6 +140,7 @@ fn main() {
                         .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]),
                 )
                 .route("/order/checkout_callback", get(checkout_callback))
+                .nest("/admin", admin_routes)
                 .route("/oauth/google/login", get(login))
                 .route("/oauth/google/callback", get(oauth_callback))
                 .route(
@@ -82,14 +148,43 @@ fn main() {
                     post(handle_lemon_squeezy_webhook)
                         .layer(middleware::from_fn(lemon_squeezy_webhook_auth::auth)),
                 )
+                .route(
+                    "/webhook/inbound_mail",
+                    post(handle_inbound_mail)
+                        .layer(DefaultBodyLimit::max(MAX_INBOUND_MAIL_SIZE))
+                        .layer(middleware::from_fn(inbound_mail_auth::auth)),
+                )
                 .with_state(state);
 
             const VERSION: &str = env!("CARGO_PKG_VERSION");
             println!("Starting server.. version: {}", VERSION);
             // run it with hyper on localhost:3000
             axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
-                .serve(app.into_make_service())
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                 .await
                 .unwrap();
         });
 }
+
+/**
+ * `replay-webhooks [event_id]`
+ * Replays the given webhook event, or all failed events.
+ */
+async fn replay_webhooks(state: &AppState, event_id: Option<String>) {
+    let event_id = event_id.map(|event_id| {
+        event_id
+            .parse::<i32>()
+            .expect("event_id is not a valid webhook event id")
+    });
+
+    let event_ids = services::webhook::replay_webhook_events(&state.conn, event_id)
+        .await
+        .expect("Failed to replay webhook events");
+
+    for event_id in event_ids {
+        match services::webhook::process_webhook_event(state, event_id).await {
+            Ok(_) => println!("Replayed webhook event {}", event_id),
+            Err(err) => println!("Failed to replay webhook event {}: {:?}", event_id, err),
+        }
+    }
+}
@@ -1,2 +1,4 @@
+pub mod admin_auth;
+pub mod inbound_mail_auth;
 pub mod jwt_auth;
 pub mod lemon_squeezy_webhook_auth;
@@ -0,0 +1,45 @@
+use std::env;
+
+use axum::{
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+    Json,
+};
+use ring::constant_time;
+
+use crate::api::AppError;
+
+pub async fn auth<T>(
+    req: Request<T>,
+    next: Next<T>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let admin_key = req
+        .headers()
+        .get("x-admin-key")
+        .ok_or((
+            StatusCode::UNAUTHORIZED,
+            Json(AppError {
+                code: "missing_admin_key",
+                message: "",
+            }),
+        ))?
+        .as_bytes();
+
+    let expected_admin_key =
+        env::var("ADMIN_API_KEY").expect("ADMIN_API_KEY is not set in .env file");
+
+    if expected_admin_key.is_empty()
+        || constant_time::verify_slices_are_equal(admin_key, expected_admin_key.as_bytes()).is_err()
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(AppError {
+                code: "invalid_admin_key",
+                message: "",
+            }),
+        ));
+    }
+
+    Ok(next.run(req).await)
+}
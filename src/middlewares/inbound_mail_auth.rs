@@ -0,0 +1,43 @@
+use std::env;
+
+use axum::{
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+    Json,
+};
+use ring::constant_time;
+
+use crate::api::AppError;
+
+pub async fn auth<T>(
+    req: Request<T>,
+    next: Next<T>,
+) -> Result<impl IntoResponse, (StatusCode, Json<AppError>)> {
+    let secret = req
+        .headers()
+        .get("x-inbound-mail-secret")
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            Json(AppError {
+                code: "missing_secret",
+                message: "",
+            }),
+        ))?
+        .as_bytes();
+
+    let expected_secret =
+        env::var("INBOUND_MAIL_SECRET").expect("INBOUND_MAIL_SECRET is not set in .env file");
+
+    if constant_time::verify_slices_are_equal(secret, expected_secret.as_bytes()).is_err() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(AppError {
+                code: "invalid_secret",
+                message: "",
+            }),
+        ));
+    }
+
+    Ok(next.run(req).await)
+}
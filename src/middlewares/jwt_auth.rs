@@ -4,14 +4,102 @@ use axum::{
     extract::State,
     http::{header, Request, StatusCode},
     middleware::Next,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     Json,
 };
 use entity::users;
 use jsonwebtoken::{decode, DecodingKey, Validation};
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use sea_orm::EntityTrait;
 
-use crate::api::{model::TokenClaims, AppError, AppState};
+use crate::{
+    api::{constants::TokenScope, model::TokenClaims, AppError, AppState},
+    services::{
+        personal_access_token::{
+            authenticate_personal_access_token, get_token_scopes, PERSONAL_ACCESS_TOKEN_PREFIX,
+        },
+        session::get_active_session,
+    },
+};
+
+/** Scope a personal access token needs for the route, `None` for routes only open to logins */
+fn get_required_scope(path: &str) -> Option<TokenScope> {
+    match path {
+        "/event/upcoming" => Some(TokenScope::EventsRead),
+        "/event/update_status" | "/event/create" | "/event/update" | "/event/delete" => {
+            Some(TokenScope::EventsWrite)
+        }
+        "/event/prepare_create" | "/event/prepare_create_from_url" => Some(TokenScope::Extract),
+        _ => None,
+    }
+}
+
+async fn find_user(
+    app_state: &AppState,
+    user_id: i32,
+) -> Result<users::Model, (StatusCode, Json<AppError>)> {
+    users::Entity::find_by_id(user_id)
+        .one(&app_state.conn)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(AppError {
+                    code: "invalid_jwt_user",
+                    message: "",
+                }),
+            )
+        })?
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            Json(AppError {
+                code: "invalid_user",
+                message: "",
+            }),
+        ))
+}
+
+/**
+ * 个人访问令牌认证
+ * 令牌只能访问其权限范围对应的接口，不能管理账号、订阅和令牌本身
+ */
+async fn personal_access_token_auth<T>(
+    app_state: &AppState,
+    token: &str,
+    mut req: Request<T>,
+    next: Next<T>,
+) -> Result<Response, (StatusCode, Json<AppError>)> {
+    let personal_access_token = authenticate_personal_access_token(&app_state.conn, token)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?
+        .ok_or((
+            StatusCode::UNAUTHORIZED,
+            Json(AppError {
+                code: "invalid_access_token",
+                message: "The access token is invalid, expired or revoked.",
+            }),
+        ))?;
+
+    let allowed = get_required_scope(req.uri().path()).map_or(false, |scope| {
+        get_token_scopes(&personal_access_token).contains(&scope)
+    });
+    if !allowed {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(AppError {
+                code: "insufficient_scope",
+                message: "The access token is not allowed to use this API.",
+            }),
+        ));
+    }
+
+    let user = find_user(app_state, personal_access_token.user_id).await?;
+
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(personal_access_token);
+
+    Ok(next.run(req).await)
+}
 
 pub async fn auth<T>(
     State(app_state): State<AppState>,
@@ -22,13 +110,8 @@ pub async fn auth<T>(
         .headers()
         .get(header::AUTHORIZATION)
         .and_then(|auth_header| auth_header.to_str().ok())
-        .and_then(|auth_value| {
-            if auth_value.starts_with("Bearer ") {
-                Some(auth_value[7..].to_owned())
-            } else {
-                None
-            }
-        })
+        .and_then(|auth_value| auth_value.strip_prefix("Bearer "))
+        .map(|token| token.to_owned())
         .ok_or((
             StatusCode::BAD_REQUEST,
             Json(AppError {
@@ -37,6 +120,10 @@ pub async fn auth<T>(
             }),
         ))?;
 
+    if token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX) {
+        return personal_access_token_auth(&app_state, &token, req, next).await;
+    }
+
     let claims = decode::<TokenClaims>(
         &token,
         &DecodingKey::from_secret(
@@ -58,29 +145,34 @@ pub async fn auth<T>(
     })?
     .claims;
 
-    let user = users::Entity::find()
-        .filter(users::Column::Email.eq(&claims.sub))
-        .one(&app_state.conn)
+    // tokens issued before the switch to user ids carry the email and fail here
+    let user_id = claims.sub.parse::<i32>().map_err(|_| {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(AppError {
+                code: "need_login",
+                message: "",
+            }),
+        )
+    })?;
+
+    let user = find_user(&app_state, user_id).await?;
+
+    // tokens of a signed-out device stop working before they expire
+    let session = get_active_session(&app_state.conn, &claims.sid)
         .await
-        .map_err(|err| {
-            sentry::capture_error(&err);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AppError {
-                    code: "invalid_jwt_user",
-                    message: "",
-                }),
-            )
-        })?
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, Json(err)))?
+        .filter(|session| session.user_id == user.id)
         .ok_or((
-            StatusCode::BAD_REQUEST,
+            StatusCode::UNAUTHORIZED,
             Json(AppError {
-                code: "invalid_user",
+                code: "need_login",
                 message: "",
             }),
         ))?;
 
     req.extensions_mut().insert(user);
+    req.extensions_mut().insert(session);
 
     Ok(next.run(req).await)
 }
@@ -6,7 +6,7 @@ use axum::{
     response::IntoResponse,
     Json,
 };
-use ring::hmac;
+use lemon_squeezy::{constants::SIGNATURE_HEADER, verify_signature};
 
 use crate::api::AppError;
 
@@ -39,7 +39,7 @@ pub async fn auth(
 
     let signature = req
         .headers()
-        .get("x-signature")
+        .get(SIGNATURE_HEADER)
         .ok_or((
             StatusCode::BAD_REQUEST,
             Json(AppError {
@@ -62,18 +62,11 @@ pub async fn auth(
 
     let (parts, body) = req.into_parts();
 
-    let hmac_key_string = env::var("LEMON_SQUEEZY_WEBHOOK_SECRET")
+    let secret = env::var("LEMON_SQUEEZY_WEBHOOK_SECRET")
         .expect("LEMON_SQUEEZY_WEBHOOK_SECRET is not set in .env file");
-    let hmac_key = hmac::Key::new(hmac::HMAC_SHA256, hmac_key_string.as_bytes());
     let body_bytes = hyper::body::to_bytes(body).await.unwrap();
-    let expected_signature = hmac::sign(&hmac_key, &body_bytes);
-    let expected_signature = expected_signature
-        .as_ref()
-        .iter()
-        .map(|n| format!("{:02x}", n))
-        .collect::<String>();
 
-    if expected_signature != signature {
+    if !verify_signature(&secret, &body_bytes, &signature) {
         return Err((
             StatusCode::FORBIDDEN,
             Json(AppError {
@@ -1,3 +1,19 @@
+pub mod auth;
+pub mod billing;
+pub mod credit;
+pub mod entitlement;
 pub mod extract_history;
+pub mod license;
+pub mod mail;
 pub mod openai;
+pub mod order;
+pub mod personal_access_token;
+pub mod plan;
+pub mod quota;
+pub mod reconciliation;
+pub mod referral;
+pub mod session;
 pub mod subscription;
+pub mod usage;
+pub mod web_page;
+pub mod webhook;
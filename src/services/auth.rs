@@ -0,0 +1,258 @@
+use std::env;
+
+use chrono::{DateTime, Duration, Utc};
+use entity::{refresh_tokens, sessions, users};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait,
+    DatabaseConnection, EntityTrait, QueryFilter, TransactionTrait,
+};
+use serde::Serialize;
+
+use super::session::{revoke_session, SessionClient};
+use crate::api::{model::TokenClaims, AppError};
+
+const DEFAULT_ACCESS_TOKEN_MINUTES: i64 = 15;
+const DEFAULT_REFRESH_TOKEN_DAYS: i64 = 30;
+
+fn database_error(err: sea_orm::DbErr) -> AppError {
+    sentry::capture_error(&err);
+    AppError {
+        code: "database_error",
+        message: "",
+    }
+}
+
+/** Lifetime of access tokens, `ACCESS_TOKEN_MINUTES`, 15 minutes by default */
+fn get_access_token_ttl() -> Duration {
+    let minutes = env::var("ACCESS_TOKEN_MINUTES")
+        .ok()
+        .and_then(|minutes| minutes.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_ACCESS_TOKEN_MINUTES);
+    Duration::minutes(minutes)
+}
+
+/** Lifetime of refresh tokens, `REFRESH_TOKEN_DAYS`, 30 days by default */
+fn get_refresh_token_ttl() -> Duration {
+    let days = env::var("REFRESH_TOKEN_DAYS")
+        .ok()
+        .and_then(|days| days.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_REFRESH_TOKEN_DAYS);
+    Duration::days(days)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|n| format!("{:02x}", n)).collect()
+}
+
+/** Only the hash of refresh tokens is stored */
+pub fn hash_token(token: &str) -> String {
+    to_hex(digest::digest(&digest::SHA256, token.as_bytes()).as_ref())
+}
+
+pub fn generate_token() -> Result<String, AppError> {
+    let mut bytes = [0u8; 32];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| AppError {
+        code: "token_generation_failed",
+        message: "",
+    })?;
+    Ok(to_hex(&bytes))
+}
+
+#[derive(Serialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    /** Seconds until the access token expires */
+    pub expires_in: i64,
+}
+
+fn issue_access_token(user: &users::Model, session_id: &str, now: DateTime<Utc>) -> String {
+    let claims = TokenClaims {
+        sub: user.id.to_string(),
+        name: user.first_name.clone(),
+        iat: now.timestamp() as usize,
+        exp: (now + get_access_token_ttl()).timestamp() as usize,
+        sid: session_id.to_owned(),
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(
+            env::var("JWT_SECRET")
+                .expect("JWT_SECRET is not set in .env file")
+                .as_ref(),
+        ),
+    )
+    .unwrap()
+}
+
+async fn create_refresh_token<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    session_id: String,
+    now: DateTime<Utc>,
+) -> Result<(String, refresh_tokens::Model), AppError> {
+    let token = generate_token()?;
+
+    let refresh_token = refresh_tokens::ActiveModel {
+        user_id: Set(user_id),
+        token_hash: Set(hash_token(&token)),
+        session_id: Set(session_id),
+        expires_at: Set(now + get_refresh_token_ttl()),
+        created_at: Set(now),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .map_err(database_error)?;
+
+    Ok((token, refresh_token))
+}
+
+/** 登录后创建会话，签发访问令牌和会话的第一个刷新令牌 */
+pub async fn issue_tokens<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    client: SessionClient,
+) -> Result<TokenPair, AppError> {
+    let now = Utc::now();
+    let session = sessions::ActiveModel {
+        id: Set(uuid::Uuid::new_v4().to_string()),
+        user_id: Set(user.id),
+        user_agent: Set(client.user_agent),
+        ip_address: Set(client.ip_address),
+        expires_at: Set(now + get_refresh_token_ttl()),
+        last_used_at: Set(now),
+        created_at: Set(now),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .map_err(database_error)?;
+    let (refresh_token, _) = create_refresh_token(db, user.id, session.id.clone(), now).await?;
+
+    Ok(TokenPair {
+        access_token: issue_access_token(user, &session.id, now),
+        refresh_token,
+        expires_in: get_access_token_ttl().num_seconds(),
+    })
+}
+
+fn invalid_refresh_token() -> AppError {
+    AppError {
+        code: "invalid_refresh_token",
+        message: "Please login again.",
+    }
+}
+
+/**
+ * 刷新令牌轮换
+ * 每个刷新令牌只能使用一次，已使用的令牌再次出现时视为泄露，撤销整个会话
+ */
+pub async fn rotate_refresh_token(
+    db: &DatabaseConnection,
+    token: &str,
+    client: SessionClient,
+) -> Result<TokenPair, AppError> {
+    let now = Utc::now();
+    let refresh_token = refresh_tokens::Entity::find()
+        .filter(refresh_tokens::Column::TokenHash.eq(hash_token(token)))
+        .one(db)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(invalid_refresh_token)?;
+
+    if refresh_token.replaced_by_id.is_some() {
+        sentry::capture_message(
+            &format!(
+                "Reuse of refresh token {} of user {}, revoking its session.",
+                refresh_token.id, refresh_token.user_id
+            ),
+            sentry::Level::Warning,
+        );
+        revoke_session(db, &refresh_token.session_id).await?;
+        return Err(invalid_refresh_token());
+    }
+    if refresh_token.revoked_at.is_some() || refresh_token.expires_at <= now {
+        return Err(invalid_refresh_token());
+    }
+
+    let user = users::Entity::find_by_id(refresh_token.user_id)
+        .one(db)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(invalid_refresh_token)?;
+
+    let txn = db.begin().await.map_err(database_error)?;
+
+    let (new_token, new_refresh_token) = create_refresh_token(
+        &txn,
+        refresh_token.user_id,
+        refresh_token.session_id.clone(),
+        now,
+    )
+    .await?;
+
+    // claim the old token, a concurrent refresh with the same token loses
+    let result = refresh_tokens::Entity::update_many()
+        .col_expr(
+            refresh_tokens::Column::ReplacedById,
+            Expr::value(new_refresh_token.id),
+        )
+        .col_expr(refresh_tokens::Column::RevokedAt, Expr::value(now))
+        .filter(
+            Condition::all()
+                .add(refresh_tokens::Column::Id.eq(refresh_token.id))
+                .add(refresh_tokens::Column::ReplacedById.is_null())
+                .add(refresh_tokens::Column::RevokedAt.is_null()),
+        )
+        .exec(&txn)
+        .await
+        .map_err(database_error)?;
+
+    if result.rows_affected == 0 {
+        return Err(invalid_refresh_token());
+    }
+
+    sessions::Entity::update_many()
+        .col_expr(sessions::Column::UserAgent, Expr::value(client.user_agent))
+        .col_expr(sessions::Column::IpAddress, Expr::value(client.ip_address))
+        .col_expr(
+            sessions::Column::ExpiresAt,
+            Expr::value(now + get_refresh_token_ttl()),
+        )
+        .col_expr(sessions::Column::LastUsedAt, Expr::value(now))
+        .filter(sessions::Column::Id.eq(&refresh_token.session_id))
+        .exec(&txn)
+        .await
+        .map_err(database_error)?;
+
+    txn.commit().await.map_err(database_error)?;
+
+    Ok(TokenPair {
+        access_token: issue_access_token(&user, &refresh_token.session_id, now),
+        refresh_token: new_token,
+        expires_in: get_access_token_ttl().num_seconds(),
+    })
+}
+
+/** 登出，撤销刷新令牌所在的会话 */
+pub async fn revoke_refresh_token<C: ConnectionTrait>(db: &C, token: &str) -> Result<(), AppError> {
+    let refresh_token = refresh_tokens::Entity::find()
+        .filter(refresh_tokens::Column::TokenHash.eq(hash_token(token)))
+        .one(db)
+        .await
+        .map_err(database_error)?;
+
+    if let Some(refresh_token) = refresh_token {
+        revoke_session(db, &refresh_token.session_id).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use std::env;
+
+use chrono::{DateTime, Utc};
+use entity::{invoices, user_subscriptions, users};
+use lemon_squeezy::{
+    GetOrdersFilter, GetOrdersParams, GetSubscriptionInvoicesFilter, GetSubscriptionInvoicesParams,
+    LemonSqueezyError, OrderObject, PageParams, SubscriptionInvoiceObject,
+};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder,
+};
+use serde::Serialize;
+
+use super::plan::get_plan_by_variant_id;
+use crate::api::{constants::PlanKind, AppError, AppState};
+
+pub const INVOICE_KIND_SUBSCRIPTION: &str = "subscription_invoice";
+pub const INVOICE_KIND_ORDER: &str = "order";
+
+const PAGE_SIZE: u32 = 100;
+
+fn database_error(err: sea_orm::DbErr) -> AppError {
+    sentry::capture_error(&err);
+    AppError {
+        code: "database_error",
+        message: "",
+    }
+}
+
+fn lemon_squeezy_error(err: LemonSqueezyError) -> AppError {
+    sentry::capture_error(&err);
+    AppError {
+        code: "failed_to_get_invoices",
+        message: "",
+    }
+}
+
+fn parse_issued_at(created_at: &str) -> DateTime<Utc> {
+    created_at
+        .parse::<DateTime<Utc>>()
+        .unwrap_or_else(|_| Utc::now())
+}
+
+/** Insert the invoice, or update the status of an existing one */
+async fn save_invoice<C: ConnectionTrait>(
+    db: &C,
+    invoice: invoices::ActiveModel,
+    kind: &str,
+    external_id: &str,
+) -> Result<(), AppError> {
+    let existing_invoice = invoices::Entity::find()
+        .filter(
+            Condition::all()
+                .add(invoices::Column::Kind.eq(kind))
+                .add(invoices::Column::ExternalId.eq(external_id)),
+        )
+        .one(db)
+        .await
+        .map_err(database_error)?;
+
+    match existing_invoice {
+        Some(existing_invoice) => {
+            let mut modified_invoice = invoice;
+            modified_invoice.id = Set(existing_invoice.id);
+            modified_invoice.update(db).await.map_err(database_error)?;
+        }
+        None => {
+            invoice.insert(db).await.map_err(database_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn save_subscription_invoice<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    invoice: &SubscriptionInvoiceObject,
+) -> Result<(), AppError> {
+    let attributes = &invoice.attributes;
+
+    save_invoice(
+        db,
+        invoices::ActiveModel {
+            user_id: Set(user_id),
+            kind: Set(INVOICE_KIND_SUBSCRIPTION.to_owned()),
+            external_id: Set(invoice.id.clone()),
+            external_subscription_id: Set(Some(attributes.subscription_id.to_string())),
+            billing_reason: Set(Some(attributes.billing_reason.clone())),
+            status: Set(attributes.status.clone()),
+            currency: Set(attributes.currency.clone()),
+            total: Set(attributes.total),
+            total_formatted: Set(attributes.total_formatted.clone()),
+            refunded: Set(attributes.refunded as i8),
+            invoice_url: Set(attributes
+                .urls
+                .as_ref()
+                .and_then(|urls| urls.invoice_url.clone())),
+            issued_at: Set(parse_issued_at(&attributes.created_at)),
+            ..Default::default()
+        },
+        INVOICE_KIND_SUBSCRIPTION,
+        &invoice.id,
+    )
+    .await
+}
+
+pub async fn save_order_invoice<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    order: &OrderObject,
+) -> Result<(), AppError> {
+    let attributes = &order.attributes;
+
+    save_invoice(
+        db,
+        invoices::ActiveModel {
+            user_id: Set(user_id),
+            kind: Set(INVOICE_KIND_ORDER.to_owned()),
+            external_id: Set(order.id.clone()),
+            external_subscription_id: Set(None),
+            billing_reason: Set(None),
+            status: Set(attributes.status.clone()),
+            currency: Set(attributes.currency.clone()),
+            total: Set(attributes.total),
+            total_formatted: Set(attributes.total_formatted.clone()),
+            refunded: Set(attributes.refunded as i8),
+            invoice_url: Set(attributes
+                .urls
+                .as_ref()
+                .and_then(|urls| urls.receipt.clone())),
+            issued_at: Set(parse_issued_at(&attributes.created_at)),
+            ..Default::default()
+        },
+        INVOICE_KIND_ORDER,
+        &order.id,
+    )
+    .await
+}
+
+/** Invoices of the user, the latest first. Returns the page and the total number of invoices. */
+pub async fn get_user_invoices<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    page: u64,
+    page_size: u64,
+) -> Result<(Vec<invoices::Model>, u64), AppError> {
+    let paginator = invoices::Entity::find()
+        .filter(invoices::Column::UserId.eq(user.id))
+        .order_by_desc(invoices::Column::IssuedAt)
+        .paginate(db, page_size);
+
+    let total = paginator.num_items().await.map_err(database_error)?;
+    let invoices = paginator.fetch_page(page).await.map_err(database_error)?;
+
+    Ok((invoices, total))
+}
+
+#[derive(Serialize, Default)]
+pub struct InvoiceBackfillReport {
+    pub saved_count: usize,
+    /** Invoices whose user could not be resolved */
+    pub skipped_ids: Vec<String>,
+}
+
+/**
+ * 补录历史账单
+ * 订阅账单通过已同步的订阅关联用户，额度包订单通过 Lemon Squeezy 客户 id 关联用户
+ * 订阅的首次付款已有订阅账单，不再按订单保存
+ */
+pub async fn backfill_invoices(app_state: &AppState) -> Result<InvoiceBackfillReport, AppError> {
+    let client = lemon_squeezy::LemonSqueezy::new(
+        env::var("LEMON_SQUEEZY_API_KEY").expect("LEMON_SQUEEZY_API_KEY is not set in .env file"),
+    );
+    let store_id = env::var("LEMON_SQUEEZY_STORE_ID")
+        .expect("LEMON_SQUEEZY_STORE_ID is not set in .env file")
+        .parse::<i32>()
+        .expect("LEMON_SQUEEZY_STORE_ID is not a valid store id");
+
+    let mut report = InvoiceBackfillReport::default();
+
+    let mut invoice_pages = client.paginate_subscription_invoices(GetSubscriptionInvoicesParams {
+        filter: GetSubscriptionInvoicesFilter {
+            store_id: Some(store_id),
+            ..Default::default()
+        },
+        page: PageParams {
+            number: 1,
+            size: PAGE_SIZE,
+        },
+    });
+    while let Some(invoices) = invoice_pages
+        .next_page()
+        .await
+        .map_err(lemon_squeezy_error)?
+    {
+        for invoice in &invoices {
+            let subscription = user_subscriptions::Entity::find()
+                .filter(
+                    user_subscriptions::Column::ExternalSubscriptionId
+                        .eq(invoice.attributes.subscription_id.to_string()),
+                )
+                .one(&app_state.conn)
+                .await
+                .map_err(database_error)?;
+
+            match subscription {
+                Some(subscription) => {
+                    save_subscription_invoice(&app_state.conn, subscription.user_id, invoice)
+                        .await?;
+                    report.saved_count += 1;
+                }
+                None => report.skipped_ids.push(invoice.id.clone()),
+            }
+        }
+    }
+
+    let mut order_pages = client.paginate_orders(GetOrdersParams {
+        filter: GetOrdersFilter {
+            store_id: Some(store_id),
+            ..Default::default()
+        },
+        page: PageParams {
+            number: 1,
+            size: PAGE_SIZE,
+        },
+    });
+    while let Some(orders) = order_pages.next_page().await.map_err(lemon_squeezy_error)? {
+        for order in &orders {
+            // only one-time purchases, subscription orders are covered by the invoices above
+            let variant_id = order
+                .attributes
+                .first_order_item
+                .as_ref()
+                .map(|item| item.variant_id);
+            let plan = match variant_id {
+                Some(variant_id) => get_plan_by_variant_id(&app_state.conn, variant_id).await?,
+                None => None,
+            };
+            if plan.map_or(true, |plan| plan.kind != PlanKind::CreditPack as i32) {
+                continue;
+            }
+
+            let user = users::Entity::find()
+                .filter(users::Column::LemonSqueezyCustomerId.eq(order.attributes.customer_id))
+                .one(&app_state.conn)
+                .await
+                .map_err(database_error)?;
+
+            match user {
+                Some(user) => {
+                    save_order_invoice(&app_state.conn, user.id, order).await?;
+                    report.saved_count += 1;
+                }
+                None => report.skipped_ids.push(order.id.clone()),
+            }
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,157 @@
+use chrono::{DateTime, Duration, Utc};
+use entity::{plans, quota_credits, users};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait,
+    EntityTrait, QueryFilter,
+};
+
+use crate::api::AppError;
+
+use super::extract_history::count_credit_usage;
+
+pub struct CreditBalance {
+    pub quota_credit_id: i32,
+    pub remaining: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn database_error(err: sea_orm::DbErr) -> AppError {
+    sentry::capture_error(&err);
+    AppError {
+        code: "database_error",
+        message: "",
+    }
+}
+
+/** Unexpired credit packs of the user with credits left, the soonest to expire first */
+pub async fn get_credit_balances<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    now: DateTime<Utc>,
+) -> Result<Vec<CreditBalance>, AppError> {
+    let credits = quota_credits::Entity::find()
+        .filter(
+            Condition::all()
+                .add(quota_credits::Column::UserId.eq(user.id))
+                .add(
+                    Condition::any()
+                        .add(quota_credits::Column::ExpiresAt.is_null())
+                        .add(quota_credits::Column::ExpiresAt.gt(now)),
+                ),
+        )
+        .all(db)
+        .await
+        .map_err(database_error)?;
+
+    let usage = count_credit_usage(db, credits.iter().map(|credit| credit.id).collect()).await?;
+
+    let mut balances: Vec<CreditBalance> = credits
+        .into_iter()
+        .map(|credit| {
+            let used_count = usage
+                .iter()
+                .find(|usage| usage.quota_credit_id == credit.id)
+                .map(|usage| usage.used_count)
+                .unwrap_or(0);
+            CreditBalance {
+                quota_credit_id: credit.id,
+                remaining: (credit.credits as i64 - used_count).max(0) as i32,
+                expires_at: credit.expires_at,
+            }
+        })
+        .filter(|balance| balance.remaining > 0)
+        .collect();
+
+    // packs without expiry are used last
+    balances.sort_by_key(|balance| (balance.expires_at.is_none(), balance.expires_at));
+
+    Ok(balances)
+}
+
+/**
+ * 发放额度包
+ * 同一个订单只发放一次
+ */
+pub async fn grant_credit_pack<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    plan: &plans::Model,
+    external_order_id: &str,
+) -> Result<(), AppError> {
+    let existing_credit = quota_credits::Entity::find()
+        .filter(quota_credits::Column::ExternalOrderId.eq(external_order_id))
+        .one(db)
+        .await
+        .map_err(database_error)?;
+
+    if existing_credit.is_some() {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let new_credit = quota_credits::ActiveModel {
+        user_id: Set(user_id),
+        plan_id: Set(Some(plan.id)),
+        external_order_id: Set(external_order_id.to_owned()),
+        credits: Set(plan.quota),
+        expires_at: Set(plan
+            .credit_validity_days
+            .map(|days| now + Duration::days(days as i64))),
+        created_at: Set(now),
+        ..Default::default()
+    };
+
+    new_credit.insert(db).await.map_err(database_error)?;
+
+    Ok(())
+}
+
+/**
+ * 发放奖励额度，例如邀请奖励
+ * `source_key` 防止重复发放，例如 `referral:1`
+ */
+pub async fn grant_bonus_credits<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    credits: i32,
+    source_key: &str,
+) -> Result<(), AppError> {
+    let existing_credit = quota_credits::Entity::find()
+        .filter(quota_credits::Column::ExternalOrderId.eq(source_key))
+        .one(db)
+        .await
+        .map_err(database_error)?;
+
+    if existing_credit.is_some() {
+        return Ok(());
+    }
+
+    let new_credit = quota_credits::ActiveModel {
+        user_id: Set(user_id),
+        plan_id: Set(None),
+        external_order_id: Set(source_key.to_owned()),
+        credits: Set(credits),
+        expires_at: Set(None),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    new_credit.insert(db).await.map_err(database_error)?;
+
+    Ok(())
+}
+
+/** 退款后收回额度包剩余的额度 */
+pub async fn revoke_credit_pack<C: ConnectionTrait>(
+    db: &C,
+    external_order_id: &str,
+) -> Result<(), AppError> {
+    quota_credits::Entity::update_many()
+        .col_expr(quota_credits::Column::ExpiresAt, Expr::value(Utc::now()))
+        .filter(quota_credits::Column::ExternalOrderId.eq(external_order_id))
+        .exec(db)
+        .await
+        .map_err(database_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::env;
+
+use chrono::{DateTime, Duration, Utc};
+use entity::user_subscriptions;
+use lemon_squeezy::SubscriptionStatus;
+use serde::Serialize;
+
+const DEFAULT_GRACE_PERIOD_DAYS: i64 = 3;
+
+/** Why the user gets the plan of the subscription or license, or the Free plan */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntitlementSource {
+    Subscription,
+    Trial,
+    /** Renewal payment failed, Lemon Squeezy is still retrying */
+    GracePeriod,
+    /** Cancelled but paid until `ends_at` */
+    Cancelled,
+    /** Activated license key of the desktop app */
+    License,
+    Free,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Entitlement {
+    pub source: EntitlementSource,
+    /** `None` while the entitlement renews automatically */
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Entitlement {
+    pub fn free() -> Self {
+        Entitlement {
+            source: EntitlementSource::Free,
+            expires_at: None,
+        }
+    }
+}
+
+/** Grace period of past due subscriptions, `SUBSCRIPTION_GRACE_PERIOD_DAYS`, 3 days by default */
+fn get_grace_period() -> Duration {
+    let days = env::var("SUBSCRIPTION_GRACE_PERIOD_DAYS")
+        .ok()
+        .and_then(|days| days.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_GRACE_PERIOD_DAYS);
+    Duration::days(days)
+}
+
+/**
+ * 订阅权益规则
+ * - active：有效
+ * - on_trial：试用期内视为付费用户，试用在 renews_at 结束
+ * - past_due：续费失败后在宽限期内有效
+ * - cancelled：在 ends_at 之前有效
+ * - paused、unpaid、expired：无效
+ */
+pub fn get_subscription_entitlement(
+    subscription: &user_subscriptions::Model,
+    now: DateTime<Utc>,
+) -> Option<Entitlement> {
+    let status = subscription
+        .status
+        .parse::<SubscriptionStatus>()
+        .unwrap_or_default();
+
+    let (source, expires_at) = match status {
+        SubscriptionStatus::Active => (EntitlementSource::Subscription, None),
+        SubscriptionStatus::OnTrial => (EntitlementSource::Trial, Some(subscription.renews_at)),
+        SubscriptionStatus::PastDue => (
+            EntitlementSource::GracePeriod,
+            Some(subscription.renews_at + get_grace_period()),
+        ),
+        SubscriptionStatus::Cancelled => (EntitlementSource::Cancelled, subscription.ends_at),
+        SubscriptionStatus::Paused
+        | SubscriptionStatus::Unpaid
+        | SubscriptionStatus::Expired
+        | SubscriptionStatus::Unknown => return None,
+    };
+
+    match expires_at {
+        Some(expires_at) if expires_at <= now => None,
+        // a cancelled subscription without an end date is already over
+        None if source == EntitlementSource::Cancelled => None,
+        _ => Some(Entitlement { source, expires_at }),
+    }
+}
+
+/** Statuses that may grant an entitlement, used to narrow down queries */
+pub fn get_entitled_statuses() -> Vec<String> {
+    [
+        SubscriptionStatus::Active,
+        SubscriptionStatus::OnTrial,
+        SubscriptionStatus::PastDue,
+        SubscriptionStatus::Cancelled,
+    ]
+    .iter()
+    .map(|status| status.to_string())
+    .collect()
+}
@@ -1,37 +1,35 @@
-use axum::extract::State;
+use chrono::Duration;
 use entity::{extract_history, users};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, EntityTrait, PaginatorTrait,
-    QueryFilter,
+    sea_query::Expr, ColumnTrait, Condition, ConnectionTrait, EntityTrait, FromQueryResult,
+    PaginatorTrait, QueryFilter, QuerySelect,
 };
 
-use crate::api::{AppError, AppState};
+use crate::api::{constants::ExtractHistoryStatus, AppError};
 
-pub async fn record_extract_history(
-    app_state: &State<AppState>,
-    user: &users::Model,
-    prompt: &str,
-) -> Result<(), AppError> {
-    extract_history::ActiveModel {
-        user_id: Set(user.id),
-        prompt: Set(Some(prompt.clone().to_owned())),
-        extract_time: Set(chrono::Utc::now()),
-        ..Default::default()
-    }
-    .save(&app_state.conn)
-    .await
-    .map_err(|err| {
-        sentry::capture_error(&err);
-        AppError {
-            code: "record_extract_history_error",
-            message: "Please try again later.",
-        }
-    })?;
-    Ok(())
+/** Reservations not committed or released within this time are no longer counted */
+pub const RESERVATION_TIMEOUT_SECONDS: i64 = 5 * 60;
+
+/** Committed extractions and pending reservations, i.e. records that use up quota */
+fn counted_condition() -> Condition {
+    let reservation_expired_time =
+        chrono::Utc::now() - Duration::seconds(RESERVATION_TIMEOUT_SECONDS);
+
+    Condition::any()
+        .add(extract_history::Column::Status.eq(ExtractHistoryStatus::Committed as i32))
+        .add(
+            Condition::all()
+                .add(extract_history::Column::Status.eq(ExtractHistoryStatus::Reserved as i32))
+                .add(extract_history::Column::ExtractTime.gt(reservation_expired_time)),
+        )
 }
 
-pub async fn count_extract_history(
-    app_state: &State<AppState>,
+/**
+ * Count committed extractions and pending reservations of the user in the given period.
+ * Extractions paid with credits are not counted against the plan quota.
+ */
+pub async fn count_extract_history<C: ConnectionTrait>(
+    db: &C,
     user: &users::Model,
     start_time: chrono::DateTime<chrono::Utc>,
     end_time: chrono::DateTime<chrono::Utc>,
@@ -41,9 +39,11 @@ pub async fn count_extract_history(
             Condition::all()
                 .add(extract_history::Column::ExtractTime.gt(start_time))
                 .add(extract_history::Column::ExtractTime.lt(end_time))
-                .add(extract_history::Column::UserId.eq(user.id)),
+                .add(extract_history::Column::UserId.eq(user.id))
+                .add(extract_history::Column::QuotaCreditId.is_null())
+                .add(counted_condition()),
         )
-        .count(&app_state.conn)
+        .count(db)
         .await
         .map_err(|_| AppError {
             code: "database_error",
@@ -57,3 +57,40 @@ pub async fn count_extract_history(
 
     Ok(result)
 }
+
+#[derive(FromQueryResult)]
+pub struct CreditUsage {
+    pub quota_credit_id: i32,
+    pub used_count: i64,
+}
+
+/** Count extractions paid with each of the given credit packs */
+pub async fn count_credit_usage<C: ConnectionTrait>(
+    db: &C,
+    quota_credit_ids: Vec<i32>,
+) -> Result<Vec<CreditUsage>, AppError> {
+    if quota_credit_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    extract_history::Entity::find()
+        .select_only()
+        .column(extract_history::Column::QuotaCreditId)
+        .column_as(Expr::col(extract_history::Column::Id).count(), "used_count")
+        .filter(
+            Condition::all()
+                .add(extract_history::Column::QuotaCreditId.is_in(quota_credit_ids))
+                .add(counted_condition()),
+        )
+        .group_by(extract_history::Column::QuotaCreditId)
+        .into_model::<CreditUsage>()
+        .all(db)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "",
+            }
+        })
+}
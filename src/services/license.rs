@@ -0,0 +1,196 @@
+use chrono::{DateTime, Utc};
+use entity::{license_bindings, user_licenses, users};
+use lemon_squeezy::{LicenseInstance, LicenseKeyInfo, LicenseKeyObject, LicenseMeta};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait,
+    EntityTrait, ModelTrait, QueryFilter, QueryOrder, SqlErr,
+};
+
+use crate::api::AppError;
+
+/** Status of a license key that grants its plan */
+pub const LICENSE_STATUS_ACTIVE: &str = "active";
+
+fn database_error(err: sea_orm::DbErr) -> AppError {
+    sentry::capture_error(&err);
+    AppError {
+        code: "database_error",
+        message: "",
+    }
+}
+
+fn parse_expires_at(expires_at: Option<&String>) -> Option<DateTime<Utc>> {
+    expires_at.and_then(|expires_at| expires_at.parse::<DateTime<Utc>>().ok())
+}
+
+/** Latest active and unexpired license of the user */
+pub async fn get_valid_license<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    now: DateTime<Utc>,
+) -> Result<Option<user_licenses::Model>, AppError> {
+    user_licenses::Entity::find()
+        .filter(
+            Condition::all()
+                .add(user_licenses::Column::UserId.eq(user.id))
+                .add(user_licenses::Column::Status.eq(LICENSE_STATUS_ACTIVE))
+                .add(
+                    Condition::any()
+                        .add(user_licenses::Column::ExpiresAt.is_null())
+                        .add(user_licenses::Column::ExpiresAt.gt(now)),
+                ),
+        )
+        .order_by_desc(user_licenses::Column::CreatedAt)
+        .one(db)
+        .await
+        .map_err(database_error)
+}
+
+/** Activated instances of the user, the latest first */
+pub async fn get_user_licenses<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+) -> Result<Vec<user_licenses::Model>, AppError> {
+    user_licenses::Entity::find()
+        .filter(user_licenses::Column::UserId.eq(user.id))
+        .order_by_desc(user_licenses::Column::CreatedAt)
+        .all(db)
+        .await
+        .map_err(database_error)
+}
+
+pub async fn get_user_license<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    instance_id: &str,
+) -> Result<Option<user_licenses::Model>, AppError> {
+    user_licenses::Entity::find()
+        .filter(
+            Condition::all()
+                .add(user_licenses::Column::UserId.eq(user.id))
+                .add(user_licenses::Column::InstanceId.eq(instance_id)),
+        )
+        .one(db)
+        .await
+        .map_err(database_error)
+}
+
+/**
+ * A license key is bound to the account that activated it first.
+ * The unique `license_key_id` makes concurrent activations of the same key from different accounts
+ * fail, so the binding must be made before the activation is used up at Lemon Squeezy.
+ * Returns whether the key is bound to the user.
+ */
+pub async fn bind_license_key<C: ConnectionTrait>(
+    db: &C,
+    license_key_id: i32,
+    user_id: i32,
+) -> Result<bool, AppError> {
+    let new_binding = license_bindings::ActiveModel {
+        license_key_id: Set(license_key_id),
+        user_id: Set(user_id),
+        ..Default::default()
+    };
+
+    match new_binding.insert(db).await {
+        Ok(_) => Ok(true),
+        Err(err) if matches!(err.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+            let binding = license_bindings::Entity::find()
+                .filter(license_bindings::Column::LicenseKeyId.eq(license_key_id))
+                .one(db)
+                .await
+                .map_err(database_error)?;
+
+            Ok(binding.map_or(false, |binding| binding.user_id == user_id))
+        }
+        Err(err) => Err(database_error(err)),
+    }
+}
+
+/** 保存激活的授权实例 */
+pub async fn save_license_activation<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    license_key: &LicenseKeyInfo,
+    instance: &LicenseInstance,
+    meta: &LicenseMeta,
+) -> Result<user_licenses::Model, AppError> {
+    let new_license = user_licenses::ActiveModel {
+        user_id: Set(user_id),
+        license_key_id: Set(license_key.id),
+        license_key: Set(license_key.key.clone()),
+        instance_id: Set(instance.id.clone()),
+        instance_name: Set(instance.name.clone()),
+        product_id: Set(meta.product_id),
+        variant_id: Set(meta.variant_id),
+        status: Set(license_key.status.clone()),
+        expires_at: Set(parse_expires_at(license_key.expires_at.as_ref())),
+        last_validated_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    new_license.insert(db).await.map_err(database_error)
+}
+
+/** Update the instance with the result of a validation */
+pub async fn save_license_validation<C: ConnectionTrait>(
+    db: &C,
+    license: &user_licenses::Model,
+    license_key: Option<&LicenseKeyInfo>,
+    valid: bool,
+) -> Result<user_licenses::Model, AppError> {
+    let mut modified_license: user_licenses::ActiveModel = license.clone().into();
+
+    match license_key {
+        Some(license_key) if valid => {
+            modified_license.status = Set(license_key.status.clone());
+            modified_license.expires_at = Set(parse_expires_at(license_key.expires_at.as_ref()));
+        }
+        Some(license_key) if license_key.status != LICENSE_STATUS_ACTIVE => {
+            modified_license.status = Set(license_key.status.clone());
+        }
+        // the instance was deactivated elsewhere, e.g. in the customer portal
+        _ => modified_license.status = Set(String::from("inactive")),
+    }
+    modified_license.last_validated_at = Set(Utc::now());
+
+    modified_license.update(db).await.map_err(database_error)
+}
+
+pub async fn delete_license<C: ConnectionTrait>(
+    db: &C,
+    license: user_licenses::Model,
+) -> Result<(), AppError> {
+    license.delete(db).await.map_err(database_error)?;
+
+    Ok(())
+}
+
+/** 授权码被禁用、过期或续期时，同步所有实例的状态 */
+pub async fn sync_license_key<C: ConnectionTrait>(
+    db: &C,
+    license_key: &LicenseKeyObject,
+) -> Result<(), AppError> {
+    let Ok(license_key_id) = license_key.id.parse::<i32>() else {
+        return Err(AppError {
+            code: "invalid_license_key_id",
+            message: "",
+        });
+    };
+
+    user_licenses::Entity::update_many()
+        .col_expr(
+            user_licenses::Column::Status,
+            Expr::value(license_key.attributes.status.clone()),
+        )
+        .col_expr(
+            user_licenses::Column::ExpiresAt,
+            Expr::value(parse_expires_at(license_key.attributes.expires_at.as_ref())),
+        )
+        .filter(user_licenses::Column::LicenseKeyId.eq(license_key_id))
+        .exec(db)
+        .await
+        .map_err(database_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use entity::inbound_mails;
+use mailparse::{DispositionType, MailHeaderMap, ParsedMail};
+use ring::digest;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait, EntityTrait,
+    QueryFilter,
+};
+
+use crate::api::AppError;
+
+use super::web_page::html_to_text;
+
+pub struct InboundMail {
+    /** `Message-ID` header, the same for retries of the inbound-parse service */
+    pub message_id: Option<String>,
+    pub subject: String,
+    pub date: Option<DateTime<Utc>>,
+    /** Addresses from `Delivered-To`, `X-Original-To`, `To` and `Cc` headers */
+    pub recipients: Vec<String>,
+    pub text: String,
+    /** Calendar content (SUMMARY, DTSTART...) of .ics parts */
+    pub calendar_events: Vec<CalendarEvent>,
+}
+
+pub struct CalendarEvent {
+    pub summary: String,
+    /** Only present when DTSTART is given in UTC */
+    pub start_time: Option<DateTime<Utc>>,
+    /** Raw properties, used as extraction input when the start time cannot be determined */
+    pub raw: String,
+}
+
+/** Parse a raw RFC 5322 message */
+pub fn parse_inbound_mail(raw: &[u8]) -> Result<InboundMail, AppError> {
+    let mail = mailparse::parse_mail(raw).map_err(|err| {
+        sentry::capture_error(&err);
+        AppError {
+            code: "invalid_mail",
+            message: "Failed to parse the mail.",
+        }
+    })?;
+
+    let message_id = mail
+        .headers
+        .get_first_value("Message-ID")
+        .map(|message_id| message_id.trim().to_owned())
+        .filter(|message_id| !message_id.is_empty());
+    let subject = mail.headers.get_first_value("Subject").unwrap_or_default();
+    let date = mail
+        .headers
+        .get_first_value("Date")
+        .and_then(|date| mailparse::dateparse(&date).ok())
+        .and_then(|timestamp| NaiveDateTime::from_timestamp_opt(timestamp, 0))
+        .map(|date| DateTime::<Utc>::from_utc(date, Utc));
+
+    let mut recipients = Vec::new();
+    for header_name in ["Delivered-To", "X-Original-To", "To", "Cc"] {
+        for header in mail.headers.get_all_headers(header_name) {
+            if let Ok(addresses) = mailparse::addrparse_header(header) {
+                for address in addresses.iter() {
+                    match address {
+                        mailparse::MailAddr::Single(single) => {
+                            recipients.push(single.addr.to_lowercase())
+                        }
+                        mailparse::MailAddr::Group(group) => recipients
+                            .extend(group.addrs.iter().map(|single| single.addr.to_lowercase())),
+                    }
+                }
+            }
+        }
+    }
+
+    let mut plain_text = None;
+    let mut html_text = None;
+    let mut calendar_events = Vec::new();
+    collect_parts(&mail, &mut plain_text, &mut html_text, &mut calendar_events);
+
+    let text = plain_text
+        .or_else(|| html_text.map(|html| html_to_text(&html)))
+        .unwrap_or_default();
+
+    Ok(InboundMail {
+        message_id,
+        subject,
+        date,
+        recipients,
+        text,
+        calendar_events,
+    })
+}
+
+fn database_error(err: sea_orm::DbErr) -> AppError {
+    sentry::capture_error(&err);
+    AppError {
+        code: "database_error",
+        message: "",
+    }
+}
+
+/** Retries deliver the same mail, identified by its `Message-ID` or by the raw mail without one */
+pub fn get_mail_dedupe_key(message_id: Option<&str>, raw: &[u8]) -> String {
+    let content = message_id
+        .map(|message_id| message_id.as_bytes())
+        .unwrap_or(raw);
+
+    digest::digest(&digest::SHA256, content)
+        .as_ref()
+        .iter()
+        .map(|n| format!("{:02x}", n))
+        .collect::<String>()
+}
+
+async fn find_inbound_mail<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    dedupe_key: &str,
+) -> Result<Option<inbound_mails::Model>, AppError> {
+    inbound_mails::Entity::find()
+        .filter(
+            Condition::all()
+                .add(inbound_mails::Column::UserId.eq(user_id))
+                .add(inbound_mails::Column::DedupeKey.eq(dedupe_key)),
+        )
+        .one(db)
+        .await
+        .map_err(database_error)
+}
+
+pub async fn is_inbound_mail_recorded<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    dedupe_key: &str,
+) -> Result<bool, AppError> {
+    Ok(find_inbound_mail(db, user_id, dedupe_key).await?.is_some())
+}
+
+/**
+ * 记录已处理的邮件
+ * 重复投递的邮件返回 false
+ */
+pub async fn record_inbound_mail<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    dedupe_key: &str,
+) -> Result<bool, AppError> {
+    let new_mail = inbound_mails::ActiveModel {
+        user_id: Set(user_id),
+        dedupe_key: Set(dedupe_key.to_owned()),
+        ..Default::default()
+    };
+
+    match new_mail.insert(db).await {
+        Ok(_) => Ok(true),
+        Err(err) => {
+            // a concurrent delivery of the same mail won the unique key
+            if find_inbound_mail(db, user_id, dedupe_key).await?.is_some() {
+                Ok(false)
+            } else {
+                Err(database_error(err))
+            }
+        }
+    }
+}
+
+fn collect_parts(
+    part: &ParsedMail,
+    plain_text: &mut Option<String>,
+    html_text: &mut Option<String>,
+    calendar_events: &mut Vec<CalendarEvent>,
+) {
+    if !part.subparts.is_empty() {
+        for subpart in part.subparts.iter() {
+            collect_parts(subpart, plain_text, html_text, calendar_events);
+        }
+        return;
+    }
+
+    let mimetype = part.ctype.mimetype.to_lowercase();
+    let disposition = part.get_content_disposition();
+    let filename = disposition
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .map(|filename| filename.to_lowercase())
+        .unwrap_or_default();
+
+    // get_body decodes quoted-printable / base64 and the charset
+    let Ok(body) = part.get_body() else {
+        return;
+    };
+
+    if mimetype == "text/calendar" || filename.ends_with(".ics") {
+        calendar_events.extend(parse_calendar(&body));
+    } else if disposition.disposition == DispositionType::Attachment {
+        // other attachments are ignored
+    } else if mimetype == "text/plain" && plain_text.is_none() {
+        *plain_text = Some(body);
+    } else if mimetype == "text/html" && html_text.is_none() {
+        *html_text = Some(body);
+    }
+}
+
+/** Read VEVENTs of an iCalendar document */
+fn parse_calendar(content: &str) -> Vec<CalendarEvent> {
+    // unfold long lines (RFC 5545 3.1)
+    let content = content
+        .replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+
+    let mut events = Vec::new();
+    let mut current: Option<(String, Option<DateTime<Utc>>, Vec<String>)> = None;
+
+    for line in content.lines() {
+        let line = line.trim_end();
+        if line.eq_ignore_ascii_case("BEGIN:VEVENT") {
+            current = Some((String::new(), None, Vec::new()));
+            continue;
+        }
+        if line.eq_ignore_ascii_case("END:VEVENT") {
+            if let Some((summary, start_time, raw)) = current.take() {
+                if !summary.is_empty() {
+                    events.push(CalendarEvent {
+                        summary,
+                        start_time,
+                        raw: raw.join("\n"),
+                    });
+                }
+            }
+            continue;
+        }
+
+        let Some((summary, start_time, raw)) = current.as_mut() else {
+            continue;
+        };
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let property = name.split(';').next().unwrap_or("").to_uppercase();
+
+        match property.as_str() {
+            "SUMMARY" => {
+                *summary = unescape_calendar_text(value);
+                raw.push(format!("SUMMARY:{}", summary));
+            }
+            "DTSTART" => {
+                if let Some(utc_value) = value.strip_suffix('Z') {
+                    *start_time = NaiveDateTime::parse_from_str(utc_value, "%Y%m%dT%H%M%S")
+                        .ok()
+                        .map(|time| DateTime::<Utc>::from_utc(time, Utc));
+                }
+                raw.push(line.to_owned());
+            }
+            "LOCATION" | "DESCRIPTION" => {
+                raw.push(format!("{}:{}", property, unescape_calendar_text(value)));
+            }
+            _ => {}
+        }
+    }
+
+    events
+}
+
+fn unescape_calendar_text(value: &str) -> String {
+    value
+        .replace("\\n", "\n")
+        .replace("\\N", "\n")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
@@ -18,7 +18,20 @@ struct Message {
     content: String,
 }
 
-pub async fn get_completion(model_name: &str, prompt: &str) -> Result<String, AppError> {
+pub struct Completion {
+    pub content: String,
+    /** Model reported by the API, usually a dated version of the requested model */
+    pub model: String,
+    pub usage: CompletionUsage,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct CompletionUsage {
+    pub prompt_tokens: i32,
+    pub completion_tokens: i32,
+}
+
+pub async fn get_completion(model_name: &str, prompt: &str) -> Result<Completion, AppError> {
     let api_endpoint =
         env::var("OPENAI_API_ENDPOINT").expect("OPENAI_API_ENDPOINT is not set in .env file");
     let api_key = env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY is not set in .env file");
@@ -98,5 +111,12 @@ pub async fn get_completion(model_name: &str, prompt: &str) -> Result<String, Ap
 
     dbg!(&openai_result);
 
-    Ok(openai_result)
+    let usage: CompletionUsage = serde_json::from_value(body["usage"].take()).unwrap_or_default();
+    let model = body["model"].as_str().unwrap_or(model_name).to_owned();
+
+    Ok(Completion {
+        content: openai_result,
+        model,
+        usage,
+    })
 }
@@ -0,0 +1,84 @@
+use std::env;
+
+use chrono::{Duration, Utc};
+use entity::orders;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait,
+    EntityTrait, QueryFilter,
+};
+
+use crate::api::{constants::OrderStatus, AppError};
+
+const DEFAULT_ORDER_EXPIRY_MINUTES: i64 = 60;
+
+/** Unpaid orders time out after `ORDER_EXPIRY_MINUTES`, 60 minutes by default */
+pub fn get_order_expiry_window() -> Duration {
+    let minutes = env::var("ORDER_EXPIRY_MINUTES")
+        .ok()
+        .and_then(|minutes| minutes.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_ORDER_EXPIRY_MINUTES);
+    Duration::minutes(minutes)
+}
+
+/** Mark unpaid orders older than the expiry window as timed out */
+pub async fn expire_unpaid_orders<C: ConnectionTrait>(db: &C) -> Result<u64, AppError> {
+    let result = orders::Entity::update_many()
+        .col_expr(
+            orders::Column::Status,
+            Expr::value(OrderStatus::Timeout as i32),
+        )
+        .filter(
+            Condition::all()
+                .add(orders::Column::Status.eq(OrderStatus::Created as i32))
+                .add(orders::Column::CreatedAt.lt(Utc::now() - get_order_expiry_window())),
+        )
+        .exec(db)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "",
+            }
+        })?;
+
+    Ok(result.rows_affected)
+}
+
+/**
+ * 更新订单状态
+ * 超时的订单仍可完成，付款可能晚于超时到达
+ */
+pub async fn update_order_status<C: ConnectionTrait>(
+    db: &C,
+    order: &orders::Model,
+    status: OrderStatus,
+) -> Result<(), AppError> {
+    let current_status = OrderStatus::try_from(order.status).ok();
+
+    let allowed = match status {
+        OrderStatus::Finished => matches!(
+            current_status,
+            Some(OrderStatus::Created) | Some(OrderStatus::Timeout)
+        ),
+        OrderStatus::Cancelled => !matches!(current_status, Some(OrderStatus::Cancelled)),
+        OrderStatus::Timeout => matches!(current_status, Some(OrderStatus::Created)),
+        OrderStatus::Created => false,
+    };
+
+    if !allowed {
+        return Ok(());
+    }
+
+    let mut modified_order: orders::ActiveModel = order.clone().into();
+    modified_order.status = Set(status as i32);
+    modified_order.save(db).await.map_err(|err| {
+        sentry::capture_error(&err);
+        AppError {
+            code: "database_error",
+            message: "",
+        }
+    })?;
+
+    Ok(())
+}
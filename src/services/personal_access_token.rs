@@ -0,0 +1,148 @@
+use chrono::{DateTime, Duration, Utc};
+use entity::{personal_access_tokens, users};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait,
+    EntityTrait, QueryFilter, QueryOrder,
+};
+
+use super::auth::{generate_token, hash_token};
+use crate::api::{constants::TokenScope, AppError};
+
+/** Tells personal access tokens apart from JWTs in the `Authorization` header */
+pub const PERSONAL_ACCESS_TOKEN_PREFIX: &str = "pat_";
+/** Characters of the token kept in clear text so that users can recognize it */
+const TOKEN_PREFIX_LENGTH: usize = 12;
+/** `last_used_at` is only written once in a while, not on every request */
+const LAST_USED_PRECISION_SECONDS: i64 = 60;
+
+fn database_error(err: sea_orm::DbErr) -> AppError {
+    sentry::capture_error(&err);
+    AppError {
+        code: "database_error",
+        message: "",
+    }
+}
+
+pub fn get_token_scopes(token: &personal_access_tokens::Model) -> Vec<TokenScope> {
+    token
+        .scopes
+        .split(',')
+        .filter_map(|scope| TokenScope::try_from(scope).ok())
+        .collect()
+}
+
+/** 创建个人访问令牌，明文令牌只在创建时返回一次 */
+pub async fn issue_personal_access_token<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    name: &str,
+    scopes: &[TokenScope],
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<(String, personal_access_tokens::Model), AppError> {
+    let token = format!("{}{}", PERSONAL_ACCESS_TOKEN_PREFIX, generate_token()?);
+
+    let mut scope_names: Vec<&str> = scopes.iter().map(|scope| scope.as_str()).collect();
+    scope_names.sort_unstable();
+    scope_names.dedup();
+
+    let personal_access_token = personal_access_tokens::ActiveModel {
+        user_id: Set(user.id),
+        name: Set(name.to_owned()),
+        token_prefix: Set(token[..TOKEN_PREFIX_LENGTH].to_owned()),
+        token_hash: Set(hash_token(&token)),
+        scopes: Set(scope_names.join(",")),
+        expires_at: Set(expires_at),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .map_err(database_error)?;
+
+    Ok((token, personal_access_token))
+}
+
+pub async fn get_user_personal_access_tokens<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+) -> Result<Vec<personal_access_tokens::Model>, AppError> {
+    personal_access_tokens::Entity::find()
+        .filter(
+            Condition::all()
+                .add(personal_access_tokens::Column::UserId.eq(user.id))
+                .add(personal_access_tokens::Column::RevokedAt.is_null()),
+        )
+        .order_by_desc(personal_access_tokens::Column::CreatedAt)
+        .all(db)
+        .await
+        .map_err(database_error)
+}
+
+/** Revoke a token of the user, returns false if there is no such token */
+pub async fn revoke_user_personal_access_token<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    id: i32,
+) -> Result<bool, AppError> {
+    let result = personal_access_tokens::Entity::update_many()
+        .col_expr(
+            personal_access_tokens::Column::RevokedAt,
+            Expr::value(Utc::now()),
+        )
+        .filter(
+            Condition::all()
+                .add(personal_access_tokens::Column::Id.eq(id))
+                .add(personal_access_tokens::Column::UserId.eq(user.id))
+                .add(personal_access_tokens::Column::RevokedAt.is_null()),
+        )
+        .exec(db)
+        .await
+        .map_err(database_error)?;
+
+    Ok(result.rows_affected > 0)
+}
+
+/**
+ * 校验个人访问令牌
+ * 返回未撤销且未过期的令牌，并记录最近使用时间
+ */
+pub async fn authenticate_personal_access_token<C: ConnectionTrait>(
+    db: &C,
+    token: &str,
+) -> Result<Option<personal_access_tokens::Model>, AppError> {
+    let now = Utc::now();
+    let personal_access_token = personal_access_tokens::Entity::find()
+        .filter(
+            Condition::all()
+                .add(personal_access_tokens::Column::TokenHash.eq(hash_token(token)))
+                .add(personal_access_tokens::Column::RevokedAt.is_null())
+                .add(
+                    Condition::any()
+                        .add(personal_access_tokens::Column::ExpiresAt.is_null())
+                        .add(personal_access_tokens::Column::ExpiresAt.gt(now)),
+                ),
+        )
+        .one(db)
+        .await
+        .map_err(database_error)?;
+
+    let Some(personal_access_token) = personal_access_token else {
+        return Ok(None);
+    };
+
+    let is_stale = personal_access_token
+        .last_used_at
+        .map_or(true, |last_used_at| {
+            now - last_used_at >= Duration::seconds(LAST_USED_PRECISION_SECONDS)
+        });
+    if is_stale {
+        personal_access_tokens::Entity::update_many()
+            .col_expr(personal_access_tokens::Column::LastUsedAt, Expr::value(now))
+            .filter(personal_access_tokens::Column::Id.eq(personal_access_token.id))
+            .exec(db)
+            .await
+            .map_err(database_error)?;
+    }
+
+    Ok(Some(personal_access_token))
+}
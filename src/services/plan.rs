@@ -0,0 +1,155 @@
+use entity::{plans, user_subscriptions};
+use sea_orm::{ColumnTrait, Condition, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder};
+
+use crate::api::{
+    constants::{PlanKind, SubscriptionType},
+    AppError,
+};
+
+/** Models allowed by the plan. The first one is used by default. */
+pub fn get_plan_models(plan: &plans::Model) -> Vec<String> {
+    plan.models
+        .split(',')
+        .map(|model| model.trim().to_owned())
+        .filter(|model| !model.is_empty())
+        .collect()
+}
+
+pub fn get_plan_features(plan: &plans::Model) -> serde_json::Value {
+    plan.features
+        .as_ref()
+        .and_then(|features| serde_json::from_str(features).ok())
+        .unwrap_or(serde_json::json!([]))
+}
+
+/** Public plan with a Lemon Squeezy variant, hidden and internal plans cannot be checked out */
+pub async fn get_purchasable_plan<C: ConnectionTrait>(
+    db: &C,
+    plan_id: i32,
+) -> Result<Option<plans::Model>, AppError> {
+    plans::Entity::find_by_id(plan_id)
+        .filter(
+            Condition::all()
+                .add(plans::Column::IsPublic.eq(1))
+                .add(plans::Column::VariantId.is_not_null()),
+        )
+        .one(db)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "",
+            }
+        })
+}
+
+pub async fn get_plan_by_variant_id<C: ConnectionTrait>(
+    db: &C,
+    variant_id: i32,
+) -> Result<Option<plans::Model>, AppError> {
+    plans::Entity::find()
+        .filter(plans::Column::VariantId.eq(variant_id))
+        .one(db)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "",
+            }
+        })
+}
+
+/** Plan of users without subscription */
+pub async fn get_free_plan<C: ConnectionTrait>(db: &C) -> Result<plans::Model, AppError> {
+    plans::Entity::find()
+        .filter(
+            Condition::all()
+                .add(plans::Column::Type.eq(SubscriptionType::Free as i32))
+                .add(plans::Column::VariantId.is_null()),
+        )
+        .order_by_asc(plans::Column::SortOrder)
+        .one(db)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "",
+            }
+        })?
+        .ok_or(AppError {
+            code: "plan_not_found",
+            message: "",
+        })
+}
+
+/** Plan of the subscription. Falls back to a plan of the same type if the variant is unknown. */
+pub async fn get_subscription_plan<C: ConnectionTrait>(
+    db: &C,
+    subscription: &user_subscriptions::Model,
+) -> Result<plans::Model, AppError> {
+    if let Some(plan) = get_plan_by_variant_id(db, subscription.variant_id).await? {
+        return Ok(plan);
+    }
+
+    plans::Entity::find()
+        .filter(
+            Condition::all()
+                .add(plans::Column::Type.eq(subscription.r#type))
+                .add(plans::Column::Kind.eq(PlanKind::Subscription as i32)),
+        )
+        .order_by_asc(plans::Column::SortOrder)
+        .one(db)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "",
+            }
+        })?
+        .ok_or(AppError {
+            code: "plan_not_found",
+            message: "",
+        })
+}
+
+/** Public subscription plans that can be purchased, cheapest first */
+pub async fn get_public_paid_plans<C: ConnectionTrait>(
+    db: &C,
+) -> Result<Vec<plans::Model>, AppError> {
+    plans::Entity::find()
+        .filter(
+            Condition::all()
+                .add(plans::Column::IsPublic.eq(1))
+                .add(plans::Column::Kind.eq(PlanKind::Subscription as i32))
+                .add(plans::Column::VariantId.is_not_null()),
+        )
+        .order_by_asc(plans::Column::SortOrder)
+        .all(db)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "",
+            }
+        })
+}
+
+pub async fn get_public_plans<C: ConnectionTrait>(db: &C) -> Result<Vec<plans::Model>, AppError> {
+    plans::Entity::find()
+        .filter(plans::Column::IsPublic.eq(1))
+        .order_by_asc(plans::Column::SortOrder)
+        .all(db)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "",
+            }
+        })
+}
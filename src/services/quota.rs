@@ -0,0 +1,197 @@
+use axum::extract::State;
+use entity::{extract_history, plans, users};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QuerySelect,
+    TransactionTrait,
+};
+
+use crate::api::{constants::ExtractHistoryStatus, AppError, AppState};
+
+use super::{
+    credit::get_credit_balances, subscription::get_user_quota_and_subscription,
+    usage::ExtractionUsage,
+};
+
+/** A quota unit held for an extraction in progress */
+pub struct QuotaReservation {
+    /** `extract_history` record of the reservation */
+    pub id: i32,
+    /** Plan the reservation is made under */
+    pub plan: plans::Model,
+}
+
+/**
+ * Reserve one quota unit for the user, from a credit pack once the plan quota is exhausted.
+ * Returns `None` when both are exhausted.
+ * Reservations of the same user are serialized by locking the user row, so concurrent requests
+ * cannot exceed the quota.
+ */
+pub async fn reserve_quota(
+    app_state: &State<AppState>,
+    user: &users::Model,
+) -> Result<Option<QuotaReservation>, AppError> {
+    let txn = app_state.conn.begin().await.map_err(|err| {
+        sentry::capture_error(&err);
+        AppError {
+            code: "database_error",
+            message: "Please try again later.",
+        }
+    })?;
+
+    users::Entity::find()
+        .filter(users::Column::Id.eq(user.id))
+        .lock_exclusive()
+        .one(&txn)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "Please try again later.",
+            }
+        })?;
+
+    let quota_and_subscription_info = get_user_quota_and_subscription(&txn, user).await?;
+
+    // the plan quota is used first, then credit packs
+    let quota_credit_id = if quota_and_subscription_info.quota_info.quota
+        > quota_and_subscription_info.quota_info.used_count
+    {
+        None
+    } else {
+        match get_credit_balances(&txn, user, chrono::Utc::now())
+            .await?
+            .first()
+        {
+            Some(balance) => Some(balance.quota_credit_id),
+            // dropping the transaction rolls it back
+            None => return Ok(None),
+        }
+    };
+
+    let reservation = extract_history::ActiveModel {
+        user_id: Set(user.id),
+        prompt: Set(None),
+        extract_time: Set(chrono::Utc::now()),
+        status: Set(ExtractHistoryStatus::Reserved as i32),
+        subscription_type: Set(Some(quota_and_subscription_info.plan.r#type)),
+        quota_credit_id: Set(quota_credit_id),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await
+    .map_err(|err| {
+        sentry::capture_error(&err);
+        AppError {
+            code: "record_extract_history_error",
+            message: "Please try again later.",
+        }
+    })?;
+
+    txn.commit().await.map_err(|err| {
+        sentry::capture_error(&err);
+        AppError {
+            code: "database_error",
+            message: "Please try again later.",
+        }
+    })?;
+
+    Ok(Some(QuotaReservation {
+        id: reservation.id,
+        plan: quota_and_subscription_info.plan,
+    }))
+}
+
+/** Mark the reservation as used */
+pub async fn commit_quota(
+    app_state: &State<AppState>,
+    reservation: &QuotaReservation,
+    prompt: &str,
+    usage: &ExtractionUsage,
+) -> Result<(), AppError> {
+    update_reservation(
+        app_state,
+        reservation,
+        ExtractHistoryStatus::Committed,
+        Some(prompt),
+        Some(usage),
+    )
+    .await
+}
+
+/**
+ * Give the reserved quota back, e.g. when the extraction failed.
+ * Token usage is still recorded if the completion was made.
+ */
+pub async fn release_quota(
+    app_state: &State<AppState>,
+    reservation: &QuotaReservation,
+    usage: Option<&ExtractionUsage>,
+) -> Result<(), AppError> {
+    update_reservation(
+        app_state,
+        reservation,
+        ExtractHistoryStatus::Released,
+        None,
+        usage,
+    )
+    .await
+}
+
+async fn update_reservation(
+    app_state: &State<AppState>,
+    reservation: &QuotaReservation,
+    status: ExtractHistoryStatus,
+    prompt: Option<&str>,
+    usage: Option<&ExtractionUsage>,
+) -> Result<(), AppError> {
+    let mut update = extract_history::Entity::update_many()
+        .col_expr(
+            extract_history::Column::Status,
+            sea_orm::sea_query::Expr::value(status as i32),
+        )
+        // keep the reservation time, it decides the quota period and the reservation timeout
+        .col_expr(
+            extract_history::Column::ExtractTime,
+            sea_orm::sea_query::Expr::col(extract_history::Column::ExtractTime).into(),
+        )
+        .filter(extract_history::Column::Id.eq(reservation.id))
+        .filter(extract_history::Column::Status.eq(ExtractHistoryStatus::Reserved as i32));
+
+    if let Some(prompt) = prompt {
+        update = update.col_expr(
+            extract_history::Column::Prompt,
+            sea_orm::sea_query::Expr::value(prompt),
+        );
+    }
+
+    if let Some(usage) = usage {
+        update = update
+            .col_expr(
+                extract_history::Column::Model,
+                sea_orm::sea_query::Expr::value(usage.model.clone()),
+            )
+            .col_expr(
+                extract_history::Column::PromptTokens,
+                sea_orm::sea_query::Expr::value(usage.prompt_tokens),
+            )
+            .col_expr(
+                extract_history::Column::CompletionTokens,
+                sea_orm::sea_query::Expr::value(usage.completion_tokens),
+            )
+            .col_expr(
+                extract_history::Column::CostMicroUsd,
+                sea_orm::sea_query::Expr::value(usage.cost_micro_usd),
+            );
+    }
+
+    update.exec(&app_state.conn).await.map_err(|err| {
+        sentry::capture_error(&err);
+        AppError {
+            code: "record_extract_history_error",
+            message: "Please try again later.",
+        }
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,228 @@
+use std::{collections::HashMap, env};
+
+use chrono::{DateTime, Utc};
+use entity::{user_subscriptions, users};
+use lemon_squeezy::{
+    GetSubscriptionsFilter, GetSubscriptionsParams, PageParams, SubscriptionObject,
+    SubscriptionStatus,
+};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use serde::Serialize;
+
+use crate::api::{AppError, AppState};
+
+use super::subscription::save_lemon_squeezy_subscription;
+
+const PAGE_SIZE: u32 = 100;
+
+#[derive(Serialize)]
+pub struct SubscriptionDiscrepancy {
+    pub external_subscription_id: String,
+    pub user_id: Option<i32>,
+    /** `drift`, `missing_local` or `missing_remote` */
+    pub kind: &'static str,
+    pub changes: Vec<String>,
+    pub fixed: bool,
+}
+
+#[derive(Serialize, Default)]
+pub struct ReconciliationReport {
+    pub checked_count: usize,
+    pub discrepancies: Vec<SubscriptionDiscrepancy>,
+}
+
+/** All subscriptions of the store */
+async fn get_remote_subscriptions() -> Result<Vec<SubscriptionObject>, AppError> {
+    let client = lemon_squeezy::LemonSqueezy::new(
+        env::var("LEMON_SQUEEZY_API_KEY").expect("LEMON_SQUEEZY_API_KEY is not set in .env file"),
+    );
+    let store_id = env::var("LEMON_SQUEEZY_STORE_ID")
+        .expect("LEMON_SQUEEZY_STORE_ID is not set in .env file")
+        .parse::<i32>()
+        .expect("LEMON_SQUEEZY_STORE_ID is not a valid store id");
+
+    client
+        .paginate_subscriptions(GetSubscriptionsParams {
+            filter: GetSubscriptionsFilter {
+                store_id: Some(store_id),
+                ..Default::default()
+            },
+            page: PageParams {
+                number: 1,
+                size: PAGE_SIZE,
+            },
+        })
+        .all()
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "failed_to_get_subscriptions",
+                message: "",
+            }
+        })
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|time| time.to_rfc3339())
+        .unwrap_or(String::from("none"))
+}
+
+fn parse_time(time: Option<&String>) -> Option<DateTime<Utc>> {
+    time.and_then(|time| time.parse::<DateTime<Utc>>().ok())
+}
+
+/** Fields of the local subscription that differ from the remote one */
+fn get_subscription_changes(
+    local: &user_subscriptions::Model,
+    remote: &SubscriptionObject,
+) -> Vec<String> {
+    let mut changes = vec![];
+
+    let remote_status = remote.attributes.status.to_string();
+    if local.status != remote_status {
+        changes.push(format!("status: {} -> {}", local.status, remote_status));
+    }
+
+    if local.variant_id != remote.attributes.variant_id {
+        changes.push(format!(
+            "variant_id: {} -> {}",
+            local.variant_id, remote.attributes.variant_id
+        ));
+    }
+
+    // timestamps are stored with a precision of seconds
+    let remote_renews_at = parse_time(Some(&remote.attributes.renews_at));
+    if remote_renews_at.map(|time| time.timestamp()) != Some(local.renews_at.timestamp()) {
+        changes.push(format!(
+            "renews_at: {} -> {}",
+            format_time(Some(local.renews_at)),
+            format_time(remote_renews_at)
+        ));
+    }
+
+    let remote_ends_at = parse_time(remote.attributes.ends_at.as_ref());
+    if remote_ends_at.map(|time| time.timestamp()) != local.ends_at.map(|time| time.timestamp()) {
+        changes.push(format!(
+            "ends_at: {} -> {}",
+            format_time(local.ends_at),
+            format_time(remote_ends_at)
+        ));
+    }
+
+    changes
+}
+
+/**
+ * 对账订阅
+ * 1. 拉取远端所有订阅，修正本地状态、续费时间、结束时间、套餐
+ * 2. 远端存在但本地缺失的订阅，通过客户 id 关联用户后补录
+ * 3. 本地存在但远端缺失的订阅，仅报告
+ */
+pub async fn reconcile_subscriptions(
+    app_state: &AppState,
+) -> Result<ReconciliationReport, AppError> {
+    let remote_subscriptions = get_remote_subscriptions().await?;
+
+    let mut local_subscriptions: HashMap<String, user_subscriptions::Model> =
+        user_subscriptions::Entity::find()
+            .all(&app_state.conn)
+            .await
+            .map_err(|err| {
+                sentry::capture_error(&err);
+                AppError {
+                    code: "database_error",
+                    message: "",
+                }
+            })?
+            .into_iter()
+            .map(|subscription| (subscription.external_subscription_id.clone(), subscription))
+            .collect();
+
+    let mut report = ReconciliationReport {
+        checked_count: remote_subscriptions.len(),
+        ..Default::default()
+    };
+
+    for remote in &remote_subscriptions {
+        let (user_id, kind, changes) = match local_subscriptions.remove(&remote.id) {
+            Some(local) => {
+                let changes = get_subscription_changes(&local, remote);
+                if changes.is_empty() {
+                    continue;
+                }
+                (Some(local.user_id), "drift", changes)
+            }
+            // expired subscriptions grant nothing, no need to backfill them
+            None if matches!(remote.attributes.status, SubscriptionStatus::Expired) => continue,
+            None => {
+                // a failed lookup is reported as unfixed, the other subscriptions are still checked
+                let user = users::Entity::find()
+                    .filter(users::Column::LemonSqueezyCustomerId.eq(remote.attributes.customer_id))
+                    .one(&app_state.conn)
+                    .await
+                    .unwrap_or_else(|err| {
+                        sentry::capture_error(&err);
+                        None
+                    });
+                (
+                    user.map(|user| user.id),
+                    "missing_local",
+                    vec![format!("customer_id: {}", remote.attributes.customer_id)],
+                )
+            }
+        };
+
+        let fixed = match user_id {
+            Some(user_id) => save_lemon_squeezy_subscription(&app_state.conn, user_id, remote)
+                .await
+                .map_err(|err| {
+                    sentry::capture_error(&err);
+                })
+                .is_ok(),
+            None => false,
+        };
+
+        report.discrepancies.push(SubscriptionDiscrepancy {
+            external_subscription_id: remote.id.clone(),
+            user_id,
+            kind,
+            changes,
+            fixed,
+        });
+    }
+
+    let expired_status = SubscriptionStatus::Expired.to_string();
+    for local in local_subscriptions.into_values() {
+        if local.status == expired_status {
+            continue;
+        }
+
+        report.discrepancies.push(SubscriptionDiscrepancy {
+            external_subscription_id: local.external_subscription_id,
+            user_id: Some(local.user_id),
+            kind: "missing_remote",
+            changes: vec![format!("status: {}", local.status)],
+            fixed: false,
+        });
+    }
+
+    tracing::info!(
+        "Reconciled {} subscriptions, found {} discrepancies.",
+        report.checked_count,
+        report.discrepancies.len()
+    );
+
+    if !report.discrepancies.is_empty() {
+        sentry::capture_message(
+            &format!(
+                "Subscription reconciliation found {} discrepancies: {}",
+                report.discrepancies.len(),
+                serde_json::to_string(&report.discrepancies).unwrap_or_default()
+            ),
+            sentry::Level::Warning,
+        );
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,239 @@
+use std::env;
+
+use chrono::Utc;
+use entity::{referrals, users};
+use lemon_squeezy::CreateDiscountParams;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait,
+    DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, TransactionTrait,
+};
+
+use crate::api::{constants::ReferralStatus, AppError};
+
+use super::credit::grant_bonus_credits;
+
+const DEFAULT_REFERRAL_DISCOUNT_PERCENT: i64 = 20;
+const DEFAULT_REFERRAL_BONUS_CREDITS: i32 = 50;
+
+fn database_error(err: sea_orm::DbErr) -> AppError {
+    sentry::capture_error(&err);
+    AppError {
+        code: "database_error",
+        message: "",
+    }
+}
+
+/** Discount of the referee's first payment, `REFERRAL_DISCOUNT_PERCENT`, 20% by default */
+fn get_referral_discount_percent() -> i64 {
+    env::var("REFERRAL_DISCOUNT_PERCENT")
+        .ok()
+        .and_then(|percent| percent.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_REFERRAL_DISCOUNT_PERCENT)
+}
+
+/** Credits of the referrer per paying referee, `REFERRAL_BONUS_CREDITS`, 50 by default */
+pub fn get_referral_bonus_credits() -> i32 {
+    env::var("REFERRAL_BONUS_CREDITS")
+        .ok()
+        .and_then(|credits| credits.parse::<i32>().ok())
+        .unwrap_or(DEFAULT_REFERRAL_BONUS_CREDITS)
+}
+
+/** Uppercase letters and numbers, as required for discount codes */
+fn generate_code(length: usize) -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..length].to_uppercase()
+}
+
+/** Referral code of the user, created on first use */
+pub async fn get_or_create_referral_code<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+) -> Result<String, AppError> {
+    if let Some(referral_code) = user.referral_code.clone() {
+        return Ok(referral_code);
+    }
+
+    let referral_code = generate_code(8);
+    let mut modified_user: users::ActiveModel = user.clone().into();
+    modified_user.referral_code = Set(Some(referral_code.clone()));
+    modified_user.update(db).await.map_err(database_error)?;
+
+    Ok(referral_code)
+}
+
+/** Create a single-use discount code for the referee */
+async fn create_referee_discount(referee: &users::Model) -> Result<(i32, String), AppError> {
+    let client = lemon_squeezy::LemonSqueezy::new(
+        env::var("LEMON_SQUEEZY_API_KEY").expect("LEMON_SQUEEZY_API_KEY is not set in .env file"),
+    );
+    let store_id = env::var("LEMON_SQUEEZY_STORE_ID")
+        .expect("LEMON_SQUEEZY_STORE_ID is not set in .env file")
+        .parse::<i32>()
+        .expect("LEMON_SQUEEZY_STORE_ID is not a valid store id");
+
+    let discount = client
+        .create_discount(CreateDiscountParams {
+            store_id,
+            name: format!("Referral of user {}", referee.id),
+            code: format!("REF{}", generate_code(8)),
+            amount: get_referral_discount_percent(),
+            amount_type: String::from("percent"),
+            duration: Some(String::from("once")),
+            max_redemptions: Some(1),
+            ..Default::default()
+        })
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "failed_to_create_discount",
+                message: "",
+            }
+        })?;
+
+    let discount_id = discount.id.parse::<i32>().map_err(|err| {
+        sentry::capture_error(&err);
+        AppError {
+            code: "invalid_discount_id",
+            message: "",
+        }
+    })?;
+
+    Ok((discount_id, discount.attributes.code))
+}
+
+/**
+ * 记录邀请注册
+ * 邀请码无效或邀请自己时忽略，被邀请人获得一次性折扣码
+ */
+pub async fn record_referral<C: ConnectionTrait>(
+    db: &C,
+    referee: &users::Model,
+    referral_code: &str,
+) -> Result<Option<referrals::Model>, AppError> {
+    let Some(referrer) = users::Entity::find()
+        .filter(users::Column::ReferralCode.eq(referral_code.to_uppercase()))
+        .one(db)
+        .await
+        .map_err(database_error)?
+        .filter(|referrer| referrer.id != referee.id)
+    else {
+        return Ok(None);
+    };
+
+    // the sign-up is recorded even if the discount cannot be created
+    let discount = create_referee_discount(referee).await.ok();
+
+    let new_referral = referrals::ActiveModel {
+        referrer_user_id: Set(referrer.id),
+        referee_user_id: Set(referee.id),
+        discount_id: Set(discount.as_ref().map(|(discount_id, _)| *discount_id)),
+        discount_code: Set(discount.map(|(_, discount_code)| discount_code)),
+        status: Set(ReferralStatus::SignedUp as i32),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    let referral = new_referral.insert(db).await.map_err(database_error)?;
+
+    Ok(Some(referral))
+}
+
+/** Discount code of a referee who has not paid yet */
+pub async fn get_referee_discount_code<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+) -> Result<Option<String>, AppError> {
+    Ok(referrals::Entity::find()
+        .filter(
+            Condition::all()
+                .add(referrals::Column::RefereeUserId.eq(user.id))
+                .add(referrals::Column::Status.eq(ReferralStatus::SignedUp as i32)),
+        )
+        .one(db)
+        .await
+        .map_err(database_error)?
+        .and_then(|referral| referral.discount_code))
+}
+
+pub struct ReferralStats {
+    pub signed_up_count: u64,
+    pub rewarded_count: u64,
+}
+
+pub async fn get_referral_stats<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+) -> Result<ReferralStats, AppError> {
+    let signed_up_count = referrals::Entity::find()
+        .filter(referrals::Column::ReferrerUserId.eq(user.id))
+        .count(db)
+        .await
+        .map_err(database_error)?;
+    let rewarded_count = referrals::Entity::find()
+        .filter(
+            Condition::all()
+                .add(referrals::Column::ReferrerUserId.eq(user.id))
+                .add(referrals::Column::Status.eq(ReferralStatus::Rewarded as i32)),
+        )
+        .count(db)
+        .await
+        .map_err(database_error)?;
+
+    Ok(ReferralStats {
+        signed_up_count,
+        rewarded_count,
+    })
+}
+
+/**
+ * 被邀请人首次付款后奖励邀请人
+ * 状态更新和发放额度在同一事务中，重复的 webhook 不会重复奖励
+ */
+pub async fn reward_referral(
+    db: &DatabaseConnection,
+    referee_user_id: i32,
+) -> Result<(), AppError> {
+    let txn = db.begin().await.map_err(database_error)?;
+
+    let result = referrals::Entity::update_many()
+        .col_expr(
+            referrals::Column::Status,
+            Expr::value(ReferralStatus::Rewarded as i32),
+        )
+        .col_expr(referrals::Column::RewardedAt, Expr::value(Utc::now()))
+        .filter(
+            Condition::all()
+                .add(referrals::Column::RefereeUserId.eq(referee_user_id))
+                .add(referrals::Column::Status.eq(ReferralStatus::SignedUp as i32)),
+        )
+        .exec(&txn)
+        .await
+        .map_err(database_error)?;
+
+    if result.rows_affected == 0 {
+        return Ok(());
+    }
+
+    let referral = referrals::Entity::find()
+        .filter(referrals::Column::RefereeUserId.eq(referee_user_id))
+        .one(&txn)
+        .await
+        .map_err(database_error)?
+        .ok_or(AppError {
+            code: "referral_not_found",
+            message: "",
+        })?;
+
+    grant_bonus_credits(
+        &txn,
+        referral.referrer_user_id,
+        get_referral_bonus_credits(),
+        &format!("referral:{}", referral.id),
+    )
+    .await?;
+
+    txn.commit().await.map_err(database_error)?;
+
+    Ok(())
+}
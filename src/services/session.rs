@@ -0,0 +1,125 @@
+use chrono::Utc;
+use entity::{refresh_tokens, sessions, users};
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, Condition, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder,
+};
+
+use crate::api::AppError;
+
+fn database_error(err: sea_orm::DbErr) -> AppError {
+    sentry::capture_error(&err);
+    AppError {
+        code: "database_error",
+        message: "",
+    }
+}
+
+/** Device the tokens were issued to */
+pub struct SessionClient {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+/** Session of an access token, `None` once it has been revoked */
+pub async fn get_active_session<C: ConnectionTrait>(
+    db: &C,
+    session_id: &str,
+) -> Result<Option<sessions::Model>, AppError> {
+    sessions::Entity::find_by_id(session_id.to_owned())
+        .filter(sessions::Column::RevokedAt.is_null())
+        .one(db)
+        .await
+        .map_err(database_error)
+}
+
+/** Signed-in devices of the user, most recently used first */
+pub async fn get_user_sessions<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+) -> Result<Vec<sessions::Model>, AppError> {
+    sessions::Entity::find()
+        .filter(
+            Condition::all()
+                .add(sessions::Column::UserId.eq(user.id))
+                .add(sessions::Column::RevokedAt.is_null())
+                .add(sessions::Column::ExpiresAt.gt(Utc::now())),
+        )
+        .order_by_desc(sessions::Column::LastUsedAt)
+        .all(db)
+        .await
+        .map_err(database_error)
+}
+
+/** 撤销会话及其全部刷新令牌，会话的访问令牌随即失效 */
+pub async fn revoke_session<C: ConnectionTrait>(db: &C, session_id: &str) -> Result<(), AppError> {
+    let now = Utc::now();
+
+    sessions::Entity::update_many()
+        .col_expr(sessions::Column::RevokedAt, Expr::value(now))
+        .filter(
+            Condition::all()
+                .add(sessions::Column::Id.eq(session_id))
+                .add(sessions::Column::RevokedAt.is_null()),
+        )
+        .exec(db)
+        .await
+        .map_err(database_error)?;
+
+    refresh_tokens::Entity::update_many()
+        .col_expr(refresh_tokens::Column::RevokedAt, Expr::value(now))
+        .filter(
+            Condition::all()
+                .add(refresh_tokens::Column::SessionId.eq(session_id))
+                .add(refresh_tokens::Column::RevokedAt.is_null()),
+        )
+        .exec(db)
+        .await
+        .map_err(database_error)?;
+
+    Ok(())
+}
+
+/** Revoke a session of the user, returns false if there is no such session */
+pub async fn revoke_user_session<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    session_id: &str,
+) -> Result<bool, AppError> {
+    let session = sessions::Entity::find_by_id(session_id.to_owned())
+        .filter(sessions::Column::UserId.eq(user.id))
+        .one(db)
+        .await
+        .map_err(database_error)?;
+
+    match session {
+        Some(session) => {
+            revoke_session(db, &session.id).await?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/** 退出其他所有设备，保留当前会话 */
+pub async fn revoke_other_sessions<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    current_session_id: &str,
+) -> Result<(), AppError> {
+    let sessions = sessions::Entity::find()
+        .filter(
+            Condition::all()
+                .add(sessions::Column::UserId.eq(user.id))
+                .add(sessions::Column::Id.ne(current_session_id))
+                .add(sessions::Column::RevokedAt.is_null()),
+        )
+        .all(db)
+        .await
+        .map_err(database_error)?;
+
+    for session in sessions {
+        revoke_session(db, &session.id).await?;
+    }
+
+    Ok(())
+}
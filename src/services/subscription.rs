@@ -1,54 +1,240 @@
 use std::env;
 
-use axum::extract::State;
-use chrono::Duration;
-use entity::{user_subscriptions, users};
-use lemon_squeezy::SubscriptionStatus;
+use chrono::{DateTime, Datelike, Months, TimeZone, Utc};
+use entity::{plans, user_licenses, user_subscriptions, users};
+use lemon_squeezy::{SubscriptionObject, SubscriptionStatus};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, EntityTrait, QueryFilter,
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait, EntityTrait,
+    QueryFilter, QueryOrder,
 };
 
-use crate::api::{constants::SubscriptionType, AppError, AppState};
+use crate::api::{AppError, AppState};
 
-use super::extract_history::count_extract_history;
+use super::{
+    credit::get_credit_balances,
+    entitlement::{
+        get_entitled_statuses, get_subscription_entitlement, Entitlement, EntitlementSource,
+    },
+    extract_history::count_extract_history,
+    license::get_valid_license,
+    plan::{get_free_plan, get_plan_by_variant_id, get_subscription_plan},
+};
 
-pub async fn get_valid_subscription(
-    app_state: &State<AppState>,
+/** Latest subscription that grants its plan according to the entitlement rules */
+pub async fn get_valid_subscription<C: ConnectionTrait>(
+    db: &C,
     user: &users::Model,
-) -> Result<Option<user_subscriptions::Model>, AppError> {
-    let result = user_subscriptions::Entity::find()
+) -> Result<Option<(user_subscriptions::Model, Entitlement)>, AppError> {
+    let subscriptions = user_subscriptions::Entity::find()
         .filter(
             Condition::all()
-                .add(user_subscriptions::Column::Status.eq(SubscriptionStatus::Active.to_string()))
+                .add(user_subscriptions::Column::Status.is_in(get_entitled_statuses()))
                 .add(user_subscriptions::Column::UserId.eq(user.id)),
         )
-        .one(&app_state.conn)
+        .order_by_desc(user_subscriptions::Column::CreatedAt)
+        .all(db)
         .await
         .map_err(|_| AppError {
             code: "database_error",
             message: "",
         })?;
 
+    let now = Utc::now();
+    let result = subscriptions.into_iter().find_map(|subscription| {
+        get_subscription_entitlement(&subscription, now)
+            .map(|entitlement| (subscription, entitlement))
+    });
+
     Ok(result)
 }
 
+/** Latest subscription of the user that has not expired, including cancelled and paused ones */
+pub async fn get_latest_subscription<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+) -> Result<Option<user_subscriptions::Model>, AppError> {
+    user_subscriptions::Entity::find()
+        .filter(
+            Condition::all()
+                .add(user_subscriptions::Column::UserId.eq(user.id))
+                .add(
+                    user_subscriptions::Column::Status.ne(SubscriptionStatus::Expired.to_string()),
+                ),
+        )
+        .order_by_desc(user_subscriptions::Column::CreatedAt)
+        .one(db)
+        .await
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "",
+            }
+        })
+}
+
 pub struct UserQuotaInfo {
     pub quota: i32,
     pub used_count: i32,
+    pub period_start: DateTime<Utc>,
+    /** The quota resets at the end of the period */
+    pub period_end: DateTime<Utc>,
+    /** Credits left in purchased credit packs, used after the quota is exhausted */
+    pub credits: i32,
 }
 
 pub struct UserQuotaAndSubscriptionInfo {
     pub quota_info: UserQuotaInfo,
     pub subscription: Option<user_subscriptions::Model>,
+    /** Licensed instance, only used without a valid subscription */
+    pub license: Option<user_licenses::Model>,
+    /** Plan of the subscription or license, or the Free plan */
+    pub plan: plans::Model,
+    pub entitlement: Entitlement,
+}
+
+pub struct QuotaPeriod {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+fn add_months(time: DateTime<Utc>, months: i32) -> Option<DateTime<Utc>> {
+    if months >= 0 {
+        time.checked_add_months(Months::new(months as u32))
+    } else {
+        time.checked_sub_months(Months::new(months.unsigned_abs()))
+    }
+}
+
+/** Calendar month containing `now`, used for users without subscription */
+pub fn get_calendar_month_period(now: DateTime<Utc>) -> QuotaPeriod {
+    let start = Utc
+        .with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .unwrap();
+    let end = add_months(start, 1).unwrap();
+
+    QuotaPeriod { start, end }
 }
 
-pub async fn get_user_quota_and_subscription(
-    app_state: &State<AppState>,
+/**
+ * Monthly period containing `now` anchored at the renewal time of the subscription, so the quota
+ * resets together with the Lemon Squeezy billing cycle. The first period starts at the
+ * subscription start time.
+ */
+pub fn get_subscription_period(
+    subscription: &user_subscriptions::Model,
+    now: DateTime<Utc>,
+) -> QuotaPeriod {
+    let anchor = subscription.renews_at;
+    // renews_at may be outdated when a renewal was missed, or far away for yearly plans
+    let mut offset = 0;
+    while offset < 1200 && add_months(anchor, offset).map_or(false, |end| end <= now) {
+        offset += 1;
+    }
+    while offset > -1200 && add_months(anchor, offset - 1).map_or(false, |end| end > now) {
+        offset -= 1;
+    }
+
+    let end = add_months(anchor, offset).unwrap_or(anchor);
+    let start = add_months(anchor, offset - 1).unwrap_or(anchor);
+
+    QuotaPeriod {
+        start: start.max(subscription.start_time),
+        end,
+    }
+}
+
+/**
+ * Quota of the subscription in the given period. When the quota changed in the middle of the
+ * period (e.g. plan upgrade), both quotas are prorated by the time they were effective.
+ * Changes from and to the Free plan are handled by the periods instead: the first period of a
+ * subscription starts at its start time with the full quota, as a full billing cycle was paid,
+ * and `get_period_after_subscription` prorates the Free quota after a subscription ended.
+ */
+pub fn get_prorated_quota(subscription: &user_subscriptions::Model, period: &QuotaPeriod) -> i32 {
+    let (Some(previous_quota), Some(quota_changed_at)) =
+        (subscription.previous_quota, subscription.quota_changed_at)
+    else {
+        return subscription.quota;
+    };
+
+    if quota_changed_at <= period.start || quota_changed_at >= period.end {
+        return subscription.quota;
+    }
+
+    let period_seconds = (period.end - period.start).num_seconds() as f64;
+    let previous_seconds = (quota_changed_at - period.start).num_seconds() as f64;
+    let prorated_quota = previous_quota as f64 * previous_seconds / period_seconds
+        + subscription.quota as f64 * (period_seconds - previous_seconds) / period_seconds;
+
+    prorated_quota.ceil() as i32
+}
+
+/**
+ * Latest end of the subscriptions within the period. A subscription is paid until `ends_at` when
+ * cancelled or expired, otherwise until the missed renewal. Ends in the future are skipped, e.g. of
+ * a paused subscription, they would start the period now and hide the usage of the month.
+ */
+fn get_subscription_ended_at(
+    subscriptions: &[user_subscriptions::Model],
+    period: &QuotaPeriod,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    subscriptions
+        .iter()
+        .map(|subscription| subscription.ends_at.unwrap_or(subscription.renews_at))
+        .filter(|ended_at| *ended_at <= now)
+        .max()
+        .filter(|ended_at| *ended_at > period.start)
+}
+
+/**
+ * Calendar month period of users without a subscription. If a subscription ended within the
+ * month, the period starts at its end so that the usage of the paid plan is not counted again,
+ * and the quota is prorated by the rest of the month.
+ */
+async fn get_period_after_subscription<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+    now: DateTime<Utc>,
+    quota: i32,
+) -> Result<(QuotaPeriod, i32), AppError> {
+    let period = get_calendar_month_period(now);
+
+    let subscriptions = user_subscriptions::Entity::find()
+        .filter(user_subscriptions::Column::UserId.eq(user.id))
+        .all(db)
+        .await
+        .map_err(|_| AppError {
+            code: "database_error",
+            message: "",
+        })?;
+
+    let Some(subscription_ended_at) = get_subscription_ended_at(&subscriptions, &period, now)
+    else {
+        return Ok((period, quota));
+    };
+
+    let period_seconds = (period.end - period.start).num_seconds() as f64;
+    let remaining_seconds = (period.end - subscription_ended_at).num_seconds() as f64;
+    let prorated_quota = (quota as f64 * remaining_seconds / period_seconds).ceil() as i32;
+
+    Ok((
+        QuotaPeriod {
+            start: subscription_ended_at,
+            end: period.end,
+        },
+        prorated_quota,
+    ))
+}
+
+pub async fn get_user_quota_and_subscription<C: ConnectionTrait>(
+    db: &C,
     user: &users::Model,
 ) -> Result<UserQuotaAndSubscriptionInfo, AppError> {
     let user = users::Entity::find()
         .filter(users::Column::Id.eq(user.id))
-        .one(&app_state.conn)
+        .one(db)
         .await
         .map_err(|_| AppError {
             code: "database_error",
@@ -59,46 +245,82 @@ pub async fn get_user_quota_and_subscription(
             message: "",
         })?;
 
-    let subscription = get_valid_subscription(&app_state, &user)
+    let now = Utc::now();
+    let valid_subscription = get_valid_subscription(db, &user)
         .await
         .map_err(|_| AppError {
             code: "database_error",
             message: "Please try again later.",
         })?;
 
-    let mut quota = 10; // Free plan
-    let mut period_start_time = chrono::Utc::now() - Duration::seconds(60 * 60 * 24 * 31);
+    // a subscription takes precedence over a license key
+    let (subscription, license, entitlement) = match valid_subscription {
+        Some((subscription, entitlement)) => (Some(subscription), None, entitlement),
+        None => match get_valid_license(db, &user, now).await? {
+            Some(license) => {
+                let entitlement = Entitlement {
+                    source: EntitlementSource::License,
+                    expires_at: license.expires_at,
+                };
+                (None, Some(license), entitlement)
+            }
+            None => (None, None, Entitlement::free()),
+        },
+    };
 
-    if subscription.is_some() {
-        let subscription_start_time = subscription.as_ref().unwrap().start_time;
-        quota = subscription.as_ref().unwrap().quota;
-        if subscription_start_time > period_start_time {
-            // 如果订阅开始时间在31天内 则以订阅开始时间为准 之前的调用不算
-            period_start_time = subscription_start_time;
+    let (period, quota, plan) = match (subscription.as_ref(), license.as_ref()) {
+        (Some(subscription), _) => {
+            let period = get_subscription_period(subscription, now);
+            let quota = get_prorated_quota(subscription, &period);
+            let plan = get_subscription_plan(db, subscription).await?;
+            (period, quota, plan)
         }
-    }
+        (None, Some(license)) => {
+            let plan = match get_plan_by_variant_id(db, license.variant_id).await? {
+                Some(plan) => plan,
+                None => get_free_plan(db).await?,
+            };
+            let (period, quota) = get_period_after_subscription(db, &user, now, plan.quota).await?;
+            (period, quota, plan)
+        }
+        (None, None) => {
+            let plan = get_free_plan(db).await?;
+            let (period, quota) = get_period_after_subscription(db, &user, now, plan.quota).await?;
+            (period, quota, plan)
+        }
+    };
 
-    let extract_count =
-        count_extract_history(&app_state, &user, period_start_time, chrono::Utc::now()).await?;
+    let extract_count = count_extract_history(db, &user, period.start, period.end).await?;
+    let credits = get_credit_balances(db, &user, now)
+        .await?
+        .iter()
+        .map(|balance| balance.remaining)
+        .sum();
 
     let result = UserQuotaAndSubscriptionInfo {
         quota_info: UserQuotaInfo {
             used_count: extract_count,
             quota,
+            period_start: period.start,
+            period_end: period.end,
+            credits,
         },
         subscription,
+        license,
+        plan,
+        entitlement,
     };
 
-    return Ok(result);
+    Ok(result)
 }
 
 /** 和LemonSqueezy同步订阅信息 需要给定LemonSqueezy的订阅ID */
 pub async fn sync_subscription_status_with_lemon_squeezy(
-    app_state: &State<AppState>,
+    app_state: &AppState,
     user: users::Model,
     external_subscription_id: i32,
 ) -> Result<(), AppError> {
-    // let user_active_subscription = get_valid_subscription(app_state, &user).await?;
+    // let user_active_subscription = get_valid_subscription(db, &user).await?;
     let client = lemon_squeezy::LemonSqueezy::new(
         env::var("LEMON_SQUEEZY_API_KEY").expect("LEMON_SQUEEZY_API_KEY is not set in .env file"),
     );
@@ -108,13 +330,22 @@ pub async fn sync_subscription_status_with_lemon_squeezy(
         .get_subscription(external_subscription_id)
         .await
         .map_err(|err| {
-            sentry::integrations::anyhow::capture_anyhow(&err);
+            sentry::capture_error(&err);
             AppError {
                 code: "failed_to_sync_subscriptions",
                 message: "failed_to_sync_subscriptions",
             }
         })?;
 
+    save_lemon_squeezy_subscription(&app_state.conn, user.id, &subscription).await
+}
+
+/** 保存LemonSqueezy的订阅信息，已存在的订阅按远端信息更新 */
+pub async fn save_lemon_squeezy_subscription<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    subscription: &SubscriptionObject,
+) -> Result<(), AppError> {
     let subscription_start_time = subscription
         .attributes
         .created_at
@@ -140,14 +371,38 @@ pub async fn sync_subscription_status_with_lemon_squeezy(
     let subscription_ends_at = subscription
         .attributes
         .ends_at
-        .clone()
-        .map(|ends_at| ends_at.parse::<chrono::DateTime<chrono::Utc>>().unwrap());
+        .as_ref()
+        .map(|ends_at| ends_at.parse::<chrono::DateTime<chrono::Utc>>())
+        .transpose()
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "invalid_subscription_ends_at",
+                message: "invalid_subscription_ends_at",
+            }
+        })?;
+
+    let plan = get_plan_by_variant_id(db, subscription.attributes.variant_id)
+        .await?
+        .ok_or_else(|| {
+            sentry::capture_message(
+                &format!(
+                    "Plan of variant {} not found.",
+                    subscription.attributes.variant_id
+                ),
+                sentry::Level::Error,
+            );
+            AppError {
+                code: "plan_not_found",
+                message: "plan_not_found",
+            }
+        })?;
 
     // user subscription information in database
 
     let user_subscription = user_subscriptions::Entity::find()
-        .filter(user_subscriptions::Column::ExternalSubscriptionId.eq(external_subscription_id))
-        .one(&app_state.conn)
+        .filter(user_subscriptions::Column::ExternalSubscriptionId.eq(subscription.id.clone()))
+        .one(db)
         .await
         .map_err(|err| {
             sentry::capture_error(&err);
@@ -157,54 +412,149 @@ pub async fn sync_subscription_status_with_lemon_squeezy(
             }
         })?;
 
-    if user_subscription.is_none() {
+    if let Some(user_subscription) = user_subscription {
+        // update user subscription with remote information
+        let mut modified_subscription: user_subscriptions::ActiveModel =
+            user_subscription.clone().into();
+
+        modified_subscription.start_time = Set(subscription_start_time);
+        modified_subscription.renews_at = Set(subscription_renews_at);
+        modified_subscription.ends_at = Set(subscription_ends_at);
+        modified_subscription.status = Set(subscription.attributes.status.to_string());
+        modified_subscription.external_subscription_id = Set(subscription.id.clone());
+        modified_subscription.product_id = Set(subscription.attributes.product_id);
+        modified_subscription.variant_id = Set(subscription.attributes.variant_id);
+        modified_subscription.r#type = Set(plan.r#type);
+
+        if user_subscription.quota != plan.quota {
+            // keep the previous quota for proration of the current period
+            modified_subscription.previous_quota = Set(Some(user_subscription.quota));
+            modified_subscription.quota_changed_at = Set(Some(Utc::now()));
+            modified_subscription.quota = Set(plan.quota);
+        }
+
+        let _ = modified_subscription.save(db).await.map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "",
+            }
+        })?;
+    } else {
         let new_subscription = user_subscriptions::ActiveModel {
-            user_id: Set(user.id),
+            user_id: Set(user_id),
             start_time: Set(subscription_start_time),
             renews_at: Set(subscription_renews_at),
             ends_at: Set(subscription_ends_at),
-            // maybe multiple plans in the future
             product_id: Set(subscription.attributes.product_id),
             variant_id: Set(subscription.attributes.variant_id),
             status: Set(subscription.attributes.status.to_string()),
             external_subscription_id: Set(subscription.id.clone()),
-            r#type: Set(SubscriptionType::Pro as i32),
-            quota: Set(125),
+            r#type: Set(plan.r#type),
+            quota: Set(plan.quota),
             ..Default::default()
         };
 
-        let _ = new_subscription
-            .save(&app_state.conn)
-            .await
-            .map_err(|err| {
-                sentry::capture_error(&err);
-                AppError {
-                    code: "database_error",
-                    message: "",
-                }
-            })?;
-    } else {
-        // update user subscription with remote information
-        let mut modified_subscription: user_subscriptions::ActiveModel =
-            user_subscription.unwrap().into();
+        let _ = new_subscription.save(db).await.map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "database_error",
+                message: "",
+            }
+        })?;
+    }
 
-        modified_subscription.start_time = Set(subscription_start_time);
-        modified_subscription.renews_at = Set(subscription_renews_at);
-        modified_subscription.ends_at = Set(subscription_ends_at);
-        modified_subscription.status = Set(subscription.attributes.status.to_string());
-        modified_subscription.external_subscription_id = Set(subscription.id.clone());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let _ = modified_subscription
-            .save(&app_state.conn)
-            .await
-            .map_err(|err| {
-                sentry::capture_error(&err);
-                AppError {
-                    code: "database_error",
-                    message: "",
-                }
-            })?;
+    fn subscription(
+        status: &str,
+        renews_at: DateTime<Utc>,
+        ends_at: Option<DateTime<Utc>>,
+    ) -> user_subscriptions::Model {
+        user_subscriptions::Model {
+            id: 1,
+            user_id: 1,
+            external_subscription_id: "1".to_owned(),
+            product_id: 1,
+            variant_id: 1,
+            status: status.to_owned(),
+            start_time: Utc.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap(),
+            renews_at,
+            ends_at,
+            r#type: 2,
+            quota: 125,
+            previous_quota: None,
+            quota_changed_at: None,
+            created_at: Utc.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap(),
+        }
     }
 
-    Ok(())
+    #[test]
+    fn subscription_ended_within_period_starts_period() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 20, 0, 0, 0).unwrap();
+        let period = get_calendar_month_period(now);
+        let ended_at = Utc.with_ymd_and_hms(2024, 3, 10, 0, 0, 0).unwrap();
+        let subscriptions = [subscription("expired", ended_at, Some(ended_at))];
+
+        assert_eq!(
+            get_subscription_ended_at(&subscriptions, &period, now),
+            Some(ended_at)
+        );
+    }
+
+    #[test]
+    fn subscription_ended_before_period_is_ignored() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 20, 0, 0, 0).unwrap();
+        let period = get_calendar_month_period(now);
+        let ended_at = Utc.with_ymd_and_hms(2024, 2, 10, 0, 0, 0).unwrap();
+        let subscriptions = [subscription("expired", ended_at, Some(ended_at))];
+
+        assert_eq!(
+            get_subscription_ended_at(&subscriptions, &period, now),
+            None
+        );
+    }
+
+    #[test]
+    fn subscription_ending_in_future_is_ignored() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 20, 0, 0, 0).unwrap();
+        let period = get_calendar_month_period(now);
+        let renews_at = Utc.with_ymd_and_hms(2024, 4, 10, 0, 0, 0).unwrap();
+        let subscriptions = [
+            subscription("paused", renews_at, None),
+            subscription("cancelled", renews_at, None),
+            subscription("unknown", renews_at, Some(renews_at)),
+        ];
+
+        // the period must not start now, which would reset the used quota on every request
+        assert_eq!(
+            get_subscription_ended_at(&subscriptions, &period, now),
+            None
+        );
+    }
+
+    #[test]
+    fn latest_past_end_is_used() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 20, 0, 0, 0).unwrap();
+        let period = get_calendar_month_period(now);
+        let ended_at = Utc.with_ymd_and_hms(2024, 3, 15, 0, 0, 0).unwrap();
+        let subscriptions = [
+            subscription("expired", ended_at, Some(ended_at)),
+            subscription(
+                "paused",
+                Utc.with_ymd_and_hms(2024, 4, 10, 0, 0, 0).unwrap(),
+                None,
+            ),
+        ];
+
+        assert_eq!(
+            get_subscription_ended_at(&subscriptions, &period, now),
+            Some(ended_at)
+        );
+    }
 }
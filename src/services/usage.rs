@@ -0,0 +1,261 @@
+use std::{collections::HashMap, env};
+
+use chrono::{DateTime, Utc};
+use entity::extract_history;
+use once_cell::sync::OnceCell;
+use sea_orm::{
+    sea_query::{Alias, Expr, Func, SimpleExpr},
+    ColumnTrait, Condition, ConnectionTrait, EntityTrait, FromQueryResult, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::api::AppError;
+
+use super::openai::Completion;
+
+/** Price in USD per 1M tokens */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+static PRICE_TABLE: OnceCell<HashMap<String, ModelPrice>> = OnceCell::new();
+
+/**
+ * Price table of models. Can be overridden with `OPENAI_PRICE_TABLE`, a JSON object like
+ * `{"gpt-4o": {"prompt": 5, "completion": 15}}`, an empty value keeps the defaults. Parsed once,
+ * called at startup so that an invalid override stops the server instead of falling back to the
+ * defaults.
+ */
+pub fn get_price_table() -> &'static HashMap<String, ModelPrice> {
+    PRICE_TABLE.get_or_init(|| match env::var("OPENAI_PRICE_TABLE") {
+        Ok(price_table) if !price_table.trim().is_empty() => serde_json::from_str(&price_table)
+            .expect("OPENAI_PRICE_TABLE is not a valid price table"),
+        _ => get_default_price_table(),
+    })
+}
+
+fn get_default_price_table() -> HashMap<String, ModelPrice> {
+    HashMap::from([
+        (
+            "gpt-3.5-turbo".to_owned(),
+            ModelPrice {
+                prompt: 0.5,
+                completion: 1.5,
+            },
+        ),
+        (
+            "gpt-4o".to_owned(),
+            ModelPrice {
+                prompt: 5.0,
+                completion: 15.0,
+            },
+        ),
+    ])
+}
+
+/** Cost of the completion in millionths of USD */
+pub fn calculate_cost(model: &str, prompt_tokens: i32, completion_tokens: i32) -> i64 {
+    // the API reports dated models like `gpt-4o-2024-05-13`, take the longest matching entry
+    let price_table = get_price_table();
+    let price = price_table
+        .iter()
+        .filter(|(name, _)| model.starts_with(name.as_str()))
+        .max_by_key(|(name, _)| name.len())
+        .map(|(_, price)| price);
+
+    match price {
+        Some(price) => {
+            // tokens * (USD per 1M tokens) = millionths of USD
+            (prompt_tokens as f64 * price.prompt + completion_tokens as f64 * price.completion)
+                .round() as i64
+        }
+        None => {
+            sentry::capture_message(
+                &format!("Missing price of model {}.", model),
+                sentry::Level::Warning,
+            );
+            0
+        }
+    }
+}
+
+pub struct ExtractionUsage {
+    pub model: String,
+    pub prompt_tokens: i32,
+    pub completion_tokens: i32,
+    pub cost_micro_usd: i64,
+}
+
+impl From<&Completion> for ExtractionUsage {
+    fn from(completion: &Completion) -> Self {
+        ExtractionUsage {
+            model: completion.model.clone(),
+            prompt_tokens: completion.usage.prompt_tokens,
+            completion_tokens: completion.usage.completion_tokens,
+            cost_micro_usd: calculate_cost(
+                &completion.model,
+                completion.usage.prompt_tokens,
+                completion.usage.completion_tokens,
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, FromQueryResult)]
+pub struct UsageByModel {
+    pub model: String,
+    pub request_count: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost_micro_usd: i64,
+}
+
+#[derive(Serialize, FromQueryResult)]
+pub struct UsageByPlan {
+    /** `SubscriptionType` at the time of extraction */
+    pub subscription_type: Option<i32>,
+    pub user_count: i64,
+    pub request_count: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost_micro_usd: i64,
+}
+
+#[derive(Serialize, FromQueryResult)]
+pub struct UsageByUser {
+    pub user_id: i32,
+    pub request_count: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost_micro_usd: i64,
+}
+
+fn sum_as_integer(column: extract_history::Column) -> SimpleExpr {
+    // SUM returns DECIMAL on MySQL
+    Func::coalesce([
+        Func::cast_as(Expr::col(column).sum(), Alias::new("SIGNED")).into(),
+        Expr::val(0).into(),
+    ])
+    .into()
+}
+
+fn usage_condition(
+    user_id: Option<i32>,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Condition {
+    let condition = Condition::all()
+        .add(extract_history::Column::ExtractTime.gte(start_time))
+        .add(extract_history::Column::ExtractTime.lt(end_time))
+        // records without usage were made before usage accounting
+        .add(extract_history::Column::Model.is_not_null());
+
+    match user_id {
+        Some(user_id) => condition.add(extract_history::Column::UserId.eq(user_id)),
+        None => condition,
+    }
+}
+
+fn select_usage_columns(
+    select: sea_orm::Select<extract_history::Entity>,
+) -> sea_orm::Select<extract_history::Entity> {
+    select
+        .column_as(
+            Expr::col(extract_history::Column::Id).count(),
+            "request_count",
+        )
+        .column_as(
+            sum_as_integer(extract_history::Column::PromptTokens),
+            "prompt_tokens",
+        )
+        .column_as(
+            sum_as_integer(extract_history::Column::CompletionTokens),
+            "completion_tokens",
+        )
+        .column_as(
+            sum_as_integer(extract_history::Column::CostMicroUsd),
+            "cost_micro_usd",
+        )
+}
+
+/** Usage grouped by model. Includes all users when `user_id` is `None`. */
+pub async fn get_usage_by_model<C: ConnectionTrait>(
+    db: &C,
+    user_id: Option<i32>,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<Vec<UsageByModel>, AppError> {
+    select_usage_columns(
+        extract_history::Entity::find()
+            .select_only()
+            .column(extract_history::Column::Model),
+    )
+    .filter(usage_condition(user_id, start_time, end_time))
+    .group_by(extract_history::Column::Model)
+    .into_model::<UsageByModel>()
+    .all(db)
+    .await
+    .map_err(|err| {
+        sentry::capture_error(&err);
+        AppError {
+            code: "database_error",
+            message: "",
+        }
+    })
+}
+
+pub async fn get_usage_by_plan<C: ConnectionTrait>(
+    db: &C,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<Vec<UsageByPlan>, AppError> {
+    select_usage_columns(
+        extract_history::Entity::find()
+            .select_only()
+            .column(extract_history::Column::SubscriptionType)
+            .column_as(Expr::cust("COUNT(DISTINCT `user_id`)"), "user_count"),
+    )
+    .filter(usage_condition(None, start_time, end_time))
+    .group_by(extract_history::Column::SubscriptionType)
+    .into_model::<UsageByPlan>()
+    .all(db)
+    .await
+    .map_err(|err| {
+        sentry::capture_error(&err);
+        AppError {
+            code: "database_error",
+            message: "",
+        }
+    })
+}
+
+/** Users with the highest cost in the period */
+pub async fn get_usage_by_user<C: ConnectionTrait>(
+    db: &C,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    limit: u64,
+) -> Result<Vec<UsageByUser>, AppError> {
+    select_usage_columns(
+        extract_history::Entity::find()
+            .select_only()
+            .column(extract_history::Column::UserId),
+    )
+    .filter(usage_condition(None, start_time, end_time))
+    .group_by(extract_history::Column::UserId)
+    .order_by_desc(Expr::cust("cost_micro_usd"))
+    .limit(limit)
+    .into_model::<UsageByUser>()
+    .all(db)
+    .await
+    .map_err(|err| {
+        sentry::capture_error(&err);
+        AppError {
+            code: "database_error",
+            message: "",
+        }
+    })
+}
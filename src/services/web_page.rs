@@ -0,0 +1,504 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use regex::Regex;
+use reqwest::{header, redirect, StatusCode};
+
+use crate::api::AppError;
+
+/** Maximum size of a fetched page body */
+const MAX_PAGE_SIZE: usize = 2 * 1024 * 1024;
+/** Timeout of a single request, including reading the body */
+const FETCH_TIMEOUT_SECONDS: u64 = 10;
+const MAX_REDIRECTS: usize = 3;
+
+pub struct JsonLdEvent {
+    pub name: String,
+    pub start_date: String,
+    pub location: Option<String>,
+}
+
+/**
+ * Fetch a web page and return its decoded HTML.
+ * Only http(s) URLs resolving to public addresses are allowed. Every redirect target is validated
+ * again and the connection is pinned to the validated address to prevent DNS rebinding.
+ */
+pub async fn fetch_page(url: &str) -> Result<String, AppError> {
+    let mut url = url::Url::parse(url).map_err(|_| AppError {
+        code: "invalid_url",
+        message: "The URL is invalid.",
+    })?;
+
+    for _ in 0..=MAX_REDIRECTS {
+        let (host, address) = resolve_public_address(&url).await?;
+
+        let client = reqwest::Client::builder()
+            .redirect(redirect::Policy::none())
+            .timeout(Duration::from_secs(FETCH_TIMEOUT_SECONDS))
+            .resolve(&host, address)
+            .build()
+            .map_err(|err| {
+                sentry::capture_error(&err);
+                AppError {
+                    code: "failed_to_fetch_url",
+                    message: "Failed to fetch the page. Please try again later.",
+                }
+            })?;
+
+        let mut response = client
+            .get(url.clone())
+            .header(header::ACCEPT, "text/html,application/xhtml+xml")
+            .send()
+            .await
+            .map_err(|_| AppError {
+                code: "failed_to_fetch_url",
+                message: "Failed to fetch the page. Please try again later.",
+            })?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or(AppError {
+                    code: "failed_to_fetch_url",
+                    message: "Failed to fetch the page. Please try again later.",
+                })?;
+            url = url.join(location).map_err(|_| AppError {
+                code: "invalid_url",
+                message: "The URL is invalid.",
+            })?;
+            continue;
+        }
+
+        if response.status() != StatusCode::OK {
+            return Err(AppError {
+                code: "failed_to_fetch_url",
+                message: "Failed to fetch the page. Please try again later.",
+            });
+        }
+
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .unwrap_or("text/html")
+            .to_lowercase();
+
+        if !content_type.starts_with("text/html") && !content_type.starts_with("application/xhtml")
+        {
+            return Err(AppError {
+                code: "unsupported_content_type",
+                message: "Only HTML pages are supported.",
+            });
+        }
+
+        if response.content_length().unwrap_or(0) as usize > MAX_PAGE_SIZE {
+            return Err(AppError {
+                code: "page_too_large",
+                message: "The page is too large.",
+            });
+        }
+
+        let mut body: Vec<u8> = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|_| AppError {
+            code: "failed_to_fetch_url",
+            message: "Failed to fetch the page. Please try again later.",
+        })? {
+            if body.len() + chunk.len() > MAX_PAGE_SIZE {
+                return Err(AppError {
+                    code: "page_too_large",
+                    message: "The page is too large.",
+                });
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        let encoding = content_type
+            .split(';')
+            .filter_map(|part| part.trim().strip_prefix("charset="))
+            .find_map(|charset| encoding_rs::Encoding::for_label(charset.as_bytes()))
+            .unwrap_or(encoding_rs::UTF_8);
+        let (html, _, _) = encoding.decode(&body);
+
+        return Ok(html.into_owned());
+    }
+
+    Err(AppError {
+        code: "too_many_redirects",
+        message: "The page redirects too many times.",
+    })
+}
+
+async fn resolve_public_address(url: &url::Url) -> Result<(String, SocketAddr), AppError> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(AppError {
+            code: "invalid_url",
+            message: "Only http and https URLs are supported.",
+        });
+    }
+
+    let host = url
+        .host_str()
+        .ok_or(AppError {
+            code: "invalid_url",
+            message: "The URL is invalid.",
+        })?
+        .to_owned();
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    // IPv6 literals keep their brackets in the URL
+    let lookup_host = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((lookup_host, port))
+        .await
+        .map_err(|_| AppError {
+            code: "failed_to_resolve_host",
+            message: "Failed to resolve the host of the URL.",
+        })?
+        .collect();
+
+    // every resolved address must be public, otherwise a second lookup may hit the private one
+    if addresses.is_empty() || addresses.iter().any(|address| !is_public_ip(address.ip())) {
+        return Err(AppError {
+            code: "url_not_allowed",
+            message: "The URL is not allowed.",
+        });
+    }
+
+    Ok((host, addresses[0]))
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_public_ipv4(mapped);
+            }
+            is_public_ipv6(ip)
+        }
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // 0.0.0.0/8
+        || octets[0] == 0
+        // 100.64.0.0/10 carrier-grade NAT
+        || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+        // 192.0.0.0/24 IETF protocol assignments
+        || (octets[0] == 192 && octets[1] == 0 && octets[2] == 0)
+        // 198.18.0.0/15 benchmarking
+        || (octets[0] == 198 && (octets[1] & 0xfe) == 18)
+        // 240.0.0.0/4 reserved
+        || octets[0] >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    // ::/96 IPv4-compatible, checked by the embedded IPv4 address
+    if segments[..6] == [0; 6] && !ip.is_loopback() && !ip.is_unspecified() {
+        return ip.to_ipv4().map_or(false, is_public_ipv4);
+    }
+    // 2002::/16 6to4, the IPv4 address follows the prefix
+    if segments[0] == 0x2002 {
+        return is_public_ipv4(Ipv4Addr::from(
+            (u32::from(segments[1]) << 16) | u32::from(segments[2]),
+        ));
+    }
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // fc00::/7 unique local
+        || (segments[0] & 0xfe00) == 0xfc00
+        // fe80::/10 link local
+        || (segments[0] & 0xffc0) == 0xfe80
+        // 2001:db8::/32 documentation
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        // 64:ff9b::/96 NAT64 may point to private IPv4 addresses
+        || (segments[0] == 0x64 && segments[1] == 0xff9b)
+        // 2001::/32 Teredo hides the IPv4 address of the relay
+        || (segments[0] == 0x2001 && segments[1] == 0))
+}
+
+/** Convert HTML to readable plain text */
+pub fn html_to_text(html: &str) -> String {
+    let invisible_regex =
+        Regex::new(r"(?is)<(script|style|noscript|svg|template)\b[^>]*>.*?</(script|style|noscript|svg|template)>|<!--.*?-->")
+            .unwrap();
+    let line_break_regex =
+        Regex::new(r"(?i)<br\s*/?>|</(p|div|li|tr|h[1-6]|section|article|header|footer|title)>")
+            .unwrap();
+    let tag_regex = Regex::new(r"(?s)<[^>]*>").unwrap();
+    let space_regex = Regex::new(r"[ \t\u{a0}]+").unwrap();
+
+    let text = invisible_regex.replace_all(html, " ");
+    let text = line_break_regex.replace_all(&text, "\n");
+    let text = tag_regex.replace_all(&text, " ");
+    let text = decode_html_entities(&text);
+
+    text.lines()
+        .map(|line| space_regex.replace_all(line, " ").trim().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+pub fn decode_html_entities(text: &str) -> String {
+    let entity_regex = Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap();
+
+    entity_regex
+        .replace_all(text, |captures: &regex::Captures| {
+            let entity = &captures[1];
+            let decoded = if let Some(hex) = entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(decimal) = entity.strip_prefix('#') {
+                decimal.parse::<u32>().ok().and_then(char::from_u32)
+            } else {
+                match entity {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some(' '),
+                    _ => None,
+                }
+            };
+            decoded
+                .map(String::from)
+                .unwrap_or_else(|| captures[0].to_owned())
+        })
+        .into_owned()
+}
+
+/** Find the first schema.org `Event` (or one of its subtypes) in the JSON-LD blocks of the page */
+pub fn extract_json_ld_event(html: &str) -> Option<JsonLdEvent> {
+    let json_ld_regex = Regex::new(
+        r#"(?is)<script[^>]*type\s*=\s*["']?application/ld\+json["']?[^>]*>(?<content>.*?)</script>"#,
+    )
+    .unwrap();
+
+    let event = json_ld_regex.captures_iter(html).find_map(|captures| {
+        let value: serde_json::Value = serde_json::from_str(captures["content"].trim()).ok()?;
+        find_json_ld_event(&value)
+    });
+
+    event
+}
+
+fn find_json_ld_event(value: &serde_json::Value) -> Option<JsonLdEvent> {
+    match value {
+        serde_json::Value::Array(items) => items.iter().find_map(find_json_ld_event),
+        serde_json::Value::Object(object) => {
+            if let Some(graph) = object.get("@graph") {
+                return find_json_ld_event(graph);
+            }
+
+            let is_event = match object.get("@type") {
+                Some(serde_json::Value::String(r#type)) => r#type.ends_with("Event"),
+                Some(serde_json::Value::Array(types)) => types
+                    .iter()
+                    .any(|r#type| r#type.as_str().unwrap_or("").ends_with("Event")),
+                _ => false,
+            };
+            if !is_event {
+                return None;
+            }
+
+            let name = object.get("name")?.as_str()?.trim();
+            let start_date = object.get("startDate")?.as_str()?.trim();
+            if name.is_empty() || start_date.is_empty() {
+                return None;
+            }
+
+            let location = match object.get("location") {
+                Some(serde_json::Value::String(location)) => Some(location.to_owned()),
+                Some(serde_json::Value::Object(location)) => location
+                    .get("name")
+                    .and_then(|name| name.as_str())
+                    .map(|name| name.to_owned()),
+                _ => None,
+            };
+
+            Some(JsonLdEvent {
+                name: decode_html_entities(name),
+                start_date: start_date.to_owned(),
+                location,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_public(ip: &str) -> bool {
+        is_public_ip(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn private_ipv4_addresses_are_rejected() {
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "224.0.0.1",
+        ] {
+            assert!(!is_public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn private_ipv6_addresses_are_rejected() {
+        for ip in ["::1", "::", "fc00::1", "fe80::1", "2001:db8::1", "ff02::1"] {
+            assert!(!is_public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn ipv6_addresses_embedding_private_ipv4_are_rejected() {
+        for ip in [
+            // IPv4-mapped
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            // IPv4-compatible
+            "::127.0.0.1",
+            "::169.254.169.254",
+            // 6to4 of 127.0.0.1 and 192.168.1.1
+            "2002:7f00:1::1",
+            "2002:c0a8:101::1",
+            // NAT64
+            "64:ff9b::a00:1",
+            // Teredo
+            "2001:0:4136:e378:8000:63bf:3fff:fdd2",
+        ] {
+            assert!(!is_public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn public_addresses_are_allowed() {
+        for ip in [
+            "8.8.8.8",
+            "1.1.1.1",
+            "::ffff:8.8.8.8",
+            "2002:808:808::1",
+            "2606:4700:4700::1111",
+        ] {
+            assert!(is_public(ip), "{} should be public", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn urls_of_private_hosts_are_rejected() {
+        for url in [
+            "http://127.0.0.1/",
+            "http://[::1]:8080/",
+            "http://10.0.0.1/",
+            "http://[::ffff:192.168.1.1]/",
+            "http://[2002:c0a8:101::1]/",
+        ] {
+            let err = resolve_public_address(&url::Url::parse(url).unwrap())
+                .await
+                .unwrap_err();
+            assert_eq!(err.code, "url_not_allowed", "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn urls_of_public_ipv6_are_resolved() {
+        let (_, address) =
+            resolve_public_address(&url::Url::parse("http://[2606:4700:4700::1111]/").unwrap())
+                .await
+                .unwrap();
+
+        assert_eq!(address, "[2606:4700:4700::1111]:80".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn urls_with_other_schemes_are_rejected() {
+        for url in ["file:///etc/passwd", "ftp://8.8.8.8/", "gopher://8.8.8.8/"] {
+            let err = resolve_public_address(&url::Url::parse(url).unwrap())
+                .await
+                .unwrap_err();
+            assert_eq!(err.code, "invalid_url", "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn urls_of_public_ips_are_resolved() {
+        let (host, address) = resolve_public_address(&url::Url::parse("https://8.8.8.8/").unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(host, "8.8.8.8");
+        assert_eq!(address, "8.8.8.8:443".parse().unwrap());
+    }
+
+    #[test]
+    fn html_to_text_keeps_visible_text() {
+        let html = r#"<html><head><title>Meetup</title><style>p { color: red; }</style></head>
+            <body><script>alert("x")</script><!-- comment --><h1>Rust&nbsp;Meetup</h1>
+            <p>Tom &amp; Jerry<br>at  the&#32;office</p></body></html>"#;
+
+        assert_eq!(
+            html_to_text(html),
+            "Meetup\nRust Meetup\nTom & Jerry\nat the office"
+        );
+    }
+
+    #[test]
+    fn decode_html_entities_keeps_unknown_entities() {
+        assert_eq!(
+            decode_html_entities("&lt;a&gt; &#x41;&#66; &unknown; &#xffffffff;"),
+            "<a> AB &unknown; &#xffffffff;"
+        );
+    }
+
+    #[test]
+    fn extract_json_ld_event_finds_event_in_graph() {
+        let html = r#"<script type="application/ld+json">{"@type": "Organization", "name": "Org"}</script>
+            <script type="application/ld+json">
+            {"@context": "https://schema.org", "@graph": [
+                {"@type": "WebPage", "name": "Page"},
+                {"@type": ["MusicEvent"], "name": "Concert &amp; Party",
+                 "startDate": "2024-05-01T19:00:00+02:00",
+                 "location": {"@type": "Place", "name": "Hall"}}
+            ]}
+            </script>"#;
+
+        let event = extract_json_ld_event(html).unwrap();
+        assert_eq!(event.name, "Concert & Party");
+        assert_eq!(event.start_date, "2024-05-01T19:00:00+02:00");
+        assert_eq!(event.location.as_deref(), Some("Hall"));
+    }
+
+    #[test]
+    fn extract_json_ld_event_requires_name_and_start_date() {
+        let html = r#"<script type="application/ld+json">
+            [{"@type": "Event", "name": "No date"}, {"@type": "Event", "startDate": "2024-05-01"}]
+            </script><script type="application/ld+json">not json</script>"#;
+
+        assert!(extract_json_ld_event(html).is_none());
+    }
+}
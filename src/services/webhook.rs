@@ -0,0 +1,590 @@
+use chrono::{Duration, Utc};
+use entity::{orders, user_subscriptions, users, webhook_events};
+use lemon_squeezy::{
+    OrderObject, SubscriptionInvoiceObject, SubscriptionObject, WebhookEvent, WebhookPayload,
+};
+use ring::digest;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait,
+    EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::api::{
+    constants::{OrderStatus, PlanKind, WebhookEventStatus},
+    AppError, AppState,
+};
+
+use super::{
+    billing::{save_order_invoice, save_subscription_invoice},
+    credit::{grant_credit_pack, revoke_credit_pack},
+    license::sync_license_key,
+    order::update_order_status,
+    plan::get_plan_by_variant_id,
+    referral::reward_referral,
+    subscription::sync_subscription_status_with_lemon_squeezy,
+};
+
+pub const LEMON_SQUEEZY_SOURCE: &str = "lemon_squeezy";
+
+/** Attempts before an event is marked as failed and left for a replay */
+const MAX_ATTEMPTS: i32 = 8;
+/** A worker that crashed while processing loses its claim after this */
+const PROCESSING_LEASE_SECONDS: i64 = 600;
+const MAX_RETRY_DELAY_SECONDS: i64 = 6 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct LemonSqueezyWebhookCustomData {
+    pub internal_order_id: Option<String>,
+}
+
+fn database_error(err: sea_orm::DbErr) -> AppError {
+    sentry::capture_error(&err);
+    AppError {
+        code: "database_error",
+        message: "",
+    }
+}
+
+/** Retries of the sender deliver the same body, so the hash of the body identifies the event */
+fn get_dedupe_key(source: &str, payload: &str) -> String {
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(source.as_bytes());
+    context.update(b":");
+    context.update(payload.as_bytes());
+    context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|n| format!("{:02x}", n))
+        .collect::<String>()
+}
+
+/** Exponential backoff starting at 1 minute */
+fn get_retry_delay(attempts: i32) -> Duration {
+    let seconds = 60_i64
+        .checked_shl(attempts.saturating_sub(1).clamp(0, 16) as u32)
+        .unwrap_or(MAX_RETRY_DELAY_SECONDS)
+        .min(MAX_RETRY_DELAY_SECONDS);
+    Duration::seconds(seconds)
+}
+
+/**
+ * 记录已验证的 webhook 事件
+ * 重复投递的事件返回 None
+ */
+pub async fn record_webhook_event<C: ConnectionTrait>(
+    db: &C,
+    source: &str,
+    event_name: &str,
+    payload: &str,
+) -> Result<Option<webhook_events::Model>, AppError> {
+    let dedupe_key = get_dedupe_key(source, payload);
+
+    let find_duplicate = || {
+        webhook_events::Entity::find()
+            .filter(webhook_events::Column::DedupeKey.eq(dedupe_key.clone()))
+            .one(db)
+    };
+
+    if find_duplicate().await.map_err(database_error)?.is_some() {
+        return Ok(None);
+    }
+
+    let new_event = webhook_events::ActiveModel {
+        source: Set(source.to_owned()),
+        event_name: Set(event_name.to_owned()),
+        dedupe_key: Set(dedupe_key.clone()),
+        payload: Set(payload.to_owned()),
+        status: Set(WebhookEventStatus::Pending as i32),
+        attempts: Set(0),
+        next_attempt_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    match new_event.insert(db).await {
+        Ok(event) => Ok(Some(event)),
+        Err(err) => {
+            // a concurrent delivery of the same event won the unique key
+            if find_duplicate().await.map_err(database_error)?.is_some() {
+                Ok(None)
+            } else {
+                Err(database_error(err))
+            }
+        }
+    }
+}
+
+/** Claim a due event so that only one worker processes it */
+async fn claim_webhook_event<C: ConnectionTrait>(
+    db: &C,
+    event_id: i32,
+) -> Result<Option<webhook_events::Model>, AppError> {
+    let now = Utc::now();
+
+    let result = webhook_events::Entity::update_many()
+        .col_expr(
+            webhook_events::Column::Status,
+            Expr::value(WebhookEventStatus::Processing as i32),
+        )
+        .col_expr(
+            webhook_events::Column::Attempts,
+            Expr::col(webhook_events::Column::Attempts).add(1),
+        )
+        .col_expr(
+            webhook_events::Column::NextAttemptAt,
+            Expr::value(now + Duration::seconds(PROCESSING_LEASE_SECONDS)),
+        )
+        .filter(
+            Condition::all()
+                .add(webhook_events::Column::Id.eq(event_id))
+                .add(webhook_events::Column::Status.is_in([
+                    WebhookEventStatus::Pending as i32,
+                    WebhookEventStatus::Processing as i32,
+                ]))
+                .add(webhook_events::Column::NextAttemptAt.lte(now)),
+        )
+        .exec(db)
+        .await
+        .map_err(database_error)?;
+
+    if result.rows_affected == 0 {
+        return Ok(None);
+    }
+
+    webhook_events::Entity::find_by_id(event_id)
+        .one(db)
+        .await
+        .map_err(database_error)
+}
+
+/**
+ * 处理 webhook 事件
+ * 失败时按指数退避重试，超过最大次数后标记为失败
+ */
+pub async fn process_webhook_event(app_state: &AppState, event_id: i32) -> Result<(), AppError> {
+    let Some(event) = claim_webhook_event(&app_state.conn, event_id).await? else {
+        return Ok(());
+    };
+
+    let result = match event.source.as_str() {
+        LEMON_SQUEEZY_SOURCE => handle_lemon_squeezy_event(app_state, &event.payload).await,
+        _ => Err(AppError {
+            code: "unknown_webhook_source",
+            message: "",
+        }),
+    };
+
+    let mut modified_event: webhook_events::ActiveModel = event.clone().into();
+
+    match &result {
+        Ok(_) => {
+            modified_event.status = Set(WebhookEventStatus::Succeeded as i32);
+            modified_event.error = Set(None);
+            modified_event.processed_at = Set(Some(Utc::now()));
+        }
+        Err(err) => {
+            let status = if event.attempts >= MAX_ATTEMPTS {
+                WebhookEventStatus::Failed
+            } else {
+                WebhookEventStatus::Pending
+            };
+            modified_event.status = Set(status as i32);
+            modified_event.error = Set(Some(format!("{}: {}", err.code, err.message)));
+            modified_event.next_attempt_at = Set(Utc::now() + get_retry_delay(event.attempts));
+        }
+    }
+
+    modified_event
+        .save(&app_state.conn)
+        .await
+        .map_err(database_error)?;
+
+    result
+}
+
+/** Process events whose next attempt is due, returns the number of processed events */
+pub async fn process_due_webhook_events(app_state: &AppState) -> Result<usize, AppError> {
+    let event_ids: Vec<i32> = webhook_events::Entity::find()
+        .select_only()
+        .column(webhook_events::Column::Id)
+        .filter(
+            Condition::all()
+                .add(webhook_events::Column::Status.is_in([
+                    WebhookEventStatus::Pending as i32,
+                    WebhookEventStatus::Processing as i32,
+                ]))
+                .add(webhook_events::Column::NextAttemptAt.lte(Utc::now())),
+        )
+        .order_by_asc(webhook_events::Column::Id)
+        .limit(50)
+        .into_tuple()
+        .all(&app_state.conn)
+        .await
+        .map_err(database_error)?;
+
+    for event_id in &event_ids {
+        let _ = process_webhook_event(app_state, *event_id)
+            .await
+            .map_err(|err| {
+                sentry::capture_error(&err);
+            });
+    }
+
+    Ok(event_ids.len())
+}
+
+/**
+ * 重放 webhook 事件
+ * 未指定事件时重放所有失败的事件，返回被重置的事件
+ */
+pub async fn replay_webhook_events<C: ConnectionTrait>(
+    db: &C,
+    event_id: Option<i32>,
+) -> Result<Vec<i32>, AppError> {
+    let condition = match event_id {
+        Some(event_id) => Condition::all()
+            .add(webhook_events::Column::Id.eq(event_id))
+            .add(webhook_events::Column::Status.ne(WebhookEventStatus::Processing as i32)),
+        None => Condition::all()
+            .add(webhook_events::Column::Status.eq(WebhookEventStatus::Failed as i32)),
+    };
+
+    let event_ids: Vec<i32> = webhook_events::Entity::find()
+        .select_only()
+        .column(webhook_events::Column::Id)
+        .filter(condition)
+        .into_tuple()
+        .all(db)
+        .await
+        .map_err(database_error)?;
+
+    if event_ids.is_empty() {
+        return Ok(event_ids);
+    }
+
+    webhook_events::Entity::update_many()
+        .col_expr(
+            webhook_events::Column::Status,
+            Expr::value(WebhookEventStatus::Pending as i32),
+        )
+        .col_expr(webhook_events::Column::Attempts, Expr::value(0))
+        .col_expr(
+            webhook_events::Column::NextAttemptAt,
+            Expr::value(Utc::now()),
+        )
+        .filter(webhook_events::Column::Id.is_in(event_ids.clone()))
+        .exec(db)
+        .await
+        .map_err(database_error)?;
+
+    Ok(event_ids)
+}
+
+async fn handle_lemon_squeezy_event(app_state: &AppState, payload: &str) -> Result<(), AppError> {
+    let payload: WebhookPayload<LemonSqueezyWebhookCustomData> = serde_json::from_str(payload)
+        .map_err(|err| {
+            sentry::capture_error(&err);
+            AppError {
+                code: "failed_to_parse_webhook_payload",
+                message: "",
+            }
+        })?;
+    let custom_data = &payload.meta.custom_data;
+
+    match payload.event {
+        WebhookEvent::OrderCreated(order) | WebhookEvent::OrderRefunded(order) => {
+            handle_order_event(app_state, custom_data, order).await
+        }
+        WebhookEvent::SubscriptionPaymentSuccess(invoice) => {
+            handle_subscription_payment_success(app_state, custom_data, invoice).await
+        }
+        WebhookEvent::SubscriptionPaymentFailed(invoice)
+        | WebhookEvent::SubscriptionPaymentRecovered(invoice)
+        | WebhookEvent::SubscriptionPaymentRefunded(invoice) => {
+            handle_subscription_invoice_event(app_state, custom_data, invoice).await
+        }
+        WebhookEvent::SubscriptionCreated(subscription)
+        | WebhookEvent::SubscriptionUpdated(subscription)
+        | WebhookEvent::SubscriptionCancelled(subscription)
+        | WebhookEvent::SubscriptionResumed(subscription)
+        | WebhookEvent::SubscriptionExpired(subscription)
+        | WebhookEvent::SubscriptionPaused(subscription)
+        | WebhookEvent::SubscriptionUnpaused(subscription) => {
+            handle_subscription_event(app_state, custom_data, subscription).await
+        }
+        WebhookEvent::LicenseKeyUpdated(license_key) => {
+            sync_license_key(&app_state.conn, &license_key).await
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn find_order(
+    app_state: &AppState,
+    custom_data: &LemonSqueezyWebhookCustomData,
+) -> Result<Option<orders::Model>, AppError> {
+    let Some(internal_order_id) = custom_data.internal_order_id.clone() else {
+        return Ok(None);
+    };
+
+    orders::Entity::find()
+        .filter(orders::Column::InternalOrderId.eq(internal_order_id))
+        .one(&app_state.conn)
+        .await
+        .map_err(database_error)
+}
+
+/** Lemon Squeezy customer of the subscription */
+struct SubscriptionCustomer<'a> {
+    customer_id: i32,
+    user_email: &'a str,
+}
+
+/**
+ * 查找订阅所属用户
+ * 1. 通过 custom_data 中的订单
+ * 2. 通过已同步的订阅
+ * 3. 通过 Lemon Squeezy 客户 id
+ * 4. 通过邮箱，仅在唯一匹配时使用
+ */
+async fn find_subscription_user(
+    app_state: &AppState,
+    order: Option<&orders::Model>,
+    external_subscription_id: i32,
+    customer: &SubscriptionCustomer<'_>,
+) -> Result<Option<users::Model>, AppError> {
+    let user_id = match order {
+        Some(order) => Some(order.user_id),
+        None => user_subscriptions::Entity::find()
+            .filter(
+                user_subscriptions::Column::ExternalSubscriptionId
+                    .eq(external_subscription_id.to_string()),
+            )
+            .one(&app_state.conn)
+            .await
+            .map_err(database_error)?
+            .map(|subscription| subscription.user_id),
+    };
+
+    if let Some(user_id) = user_id {
+        return users::Entity::find_by_id(user_id)
+            .one(&app_state.conn)
+            .await
+            .map_err(database_error);
+    }
+
+    let user = users::Entity::find()
+        .filter(users::Column::LemonSqueezyCustomerId.eq(customer.customer_id))
+        .one(&app_state.conn)
+        .await
+        .map_err(database_error)?;
+
+    if user.is_some() {
+        return Ok(user);
+    }
+
+    let mut users_with_email = users::Entity::find()
+        .filter(users::Column::Email.eq(customer.user_email))
+        .limit(2)
+        .all(&app_state.conn)
+        .await
+        .map_err(database_error)?;
+
+    if users_with_email.len() == 1 {
+        Ok(users_with_email.pop())
+    } else {
+        Ok(None)
+    }
+}
+
+/** Remember the customer so that later events resolve the user even if the email changes */
+async fn save_customer_id(
+    app_state: &AppState,
+    user: &users::Model,
+    customer_id: i32,
+) -> Result<(), AppError> {
+    if user.lemon_squeezy_customer_id == Some(customer_id) {
+        return Ok(());
+    }
+
+    let mut modified_user: users::ActiveModel = user.clone().into();
+    modified_user.lemon_squeezy_customer_id = Set(Some(customer_id));
+    modified_user
+        .save(&app_state.conn)
+        .await
+        .map_err(database_error)?;
+
+    Ok(())
+}
+
+/** 同步订阅状态，返回订阅所属用户的 id */
+async fn sync_subscription(
+    app_state: &AppState,
+    order: Option<&orders::Model>,
+    external_subscription_id: i32,
+    customer: SubscriptionCustomer<'_>,
+) -> Result<Option<i32>, AppError> {
+    let Some(user) =
+        find_subscription_user(app_state, order, external_subscription_id, &customer).await?
+    else {
+        sentry::capture_message(
+            &format!(
+                "User of subscription {} not found for webhook event.",
+                external_subscription_id
+            ),
+            sentry::Level::Error,
+        );
+        return Ok(None);
+    };
+
+    let user_id = user.id;
+    save_customer_id(app_state, &user, customer.customer_id).await?;
+    sync_subscription_status_with_lemon_squeezy(app_state, user, external_subscription_id).await?;
+
+    Ok(Some(user_id))
+}
+
+/**
+ * 订单创建、退款
+ * 已付款的订单标记为完成，退款的订单标记为取消
+ * 额度包订单付款后发放额度，退款后收回
+ */
+async fn handle_order_event(
+    app_state: &AppState,
+    custom_data: &LemonSqueezyWebhookCustomData,
+    remote_order: OrderObject,
+) -> Result<(), AppError> {
+    let Some(order) = find_order(app_state, custom_data).await? else {
+        return Ok(());
+    };
+
+    let status = if remote_order.attributes.refunded || remote_order.attributes.status == "refunded"
+    {
+        OrderStatus::Cancelled
+    } else if remote_order.attributes.status == "paid" {
+        OrderStatus::Finished
+    } else {
+        return Ok(());
+    };
+
+    update_order_status(&app_state.conn, &order, status).await?;
+
+    // the first payment of a subscription is saved as its invoice by `subscription_payment_success`
+    let plan = get_plan_by_variant_id(&app_state.conn, order.variant_id).await?;
+    if let Some(plan) = plan.filter(|plan| plan.kind == PlanKind::CreditPack as i32) {
+        save_order_invoice(&app_state.conn, order.user_id, &remote_order).await?;
+        if remote_order.attributes.status == "paid" && !remote_order.attributes.refunded {
+            grant_credit_pack(&app_state.conn, order.user_id, &plan, &remote_order.id).await?;
+        } else {
+            revoke_credit_pack(&app_state.conn, &remote_order.id).await?;
+        }
+    }
+
+    if let Some(user) = users::Entity::find_by_id(order.user_id)
+        .one(&app_state.conn)
+        .await
+        .map_err(database_error)?
+    {
+        save_customer_id(app_state, &user, remote_order.attributes.customer_id).await?;
+    }
+
+    Ok(())
+}
+
+/**
+ * 订阅成功
+ * 1. 更新订单状态
+ * 2. 同步订阅状态
+ * 3. 保存账单
+ * 4. 首次实际付款时奖励邀请人
+ */
+async fn handle_subscription_payment_success(
+    app_state: &AppState,
+    custom_data: &LemonSqueezyWebhookCustomData,
+    invoice: SubscriptionInvoiceObject,
+) -> Result<(), AppError> {
+    let order = find_order(app_state, custom_data).await?;
+
+    if let Some(order) = &order {
+        update_order_status(&app_state.conn, order, OrderStatus::Finished).await?;
+    }
+
+    let user_id = sync_subscription(
+        app_state,
+        order.as_ref(),
+        invoice.attributes.subscription_id,
+        SubscriptionCustomer {
+            customer_id: invoice.attributes.customer_id,
+            user_email: &invoice.attributes.user_email,
+        },
+    )
+    .await?;
+
+    if let Some(user_id) = user_id {
+        save_subscription_invoice(&app_state.conn, user_id, &invoice).await?;
+
+        // $0 invoices of trials or full discounts do not earn the reward
+        let attributes = &invoice.attributes;
+        if attributes.status == "paid" && attributes.total > 0 && !attributes.refunded {
+            reward_referral(&app_state.conn, user_id).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/** 续费失败、恢复、退款，同步订阅状态并保存账单 */
+async fn handle_subscription_invoice_event(
+    app_state: &AppState,
+    custom_data: &LemonSqueezyWebhookCustomData,
+    invoice: SubscriptionInvoiceObject,
+) -> Result<(), AppError> {
+    let order = find_order(app_state, custom_data).await?;
+
+    let user_id = sync_subscription(
+        app_state,
+        order.as_ref(),
+        invoice.attributes.subscription_id,
+        SubscriptionCustomer {
+            customer_id: invoice.attributes.customer_id,
+            user_email: &invoice.attributes.user_email,
+        },
+    )
+    .await?;
+
+    if let Some(user_id) = user_id {
+        save_subscription_invoice(&app_state.conn, user_id, &invoice).await?;
+    }
+
+    Ok(())
+}
+
+/** 订阅创建、变更、取消、恢复、过期、暂停，同步订阅状态 */
+async fn handle_subscription_event(
+    app_state: &AppState,
+    custom_data: &LemonSqueezyWebhookCustomData,
+    subscription: SubscriptionObject,
+) -> Result<(), AppError> {
+    let external_subscription_id = subscription.id.parse::<i32>().map_err(|err| {
+        sentry::capture_error(&err);
+        AppError {
+            code: "invalid_subscription_id",
+            message: "",
+        }
+    })?;
+    let order = find_order(app_state, custom_data).await?;
+
+    sync_subscription(
+        app_state,
+        order.as_ref(),
+        external_subscription_id,
+        SubscriptionCustomer {
+            customer_id: subscription.attributes.customer_id,
+            user_email: &subscription.attributes.user_email,
+        },
+    )
+    .await?;
+
+    Ok(())
+}